@@ -0,0 +1,192 @@
+use crate::geo::Point;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float32Type, Float64Type};
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, RecordBatch, UInt64Array};
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use arrow_select::concat::concat_batches;
+use arrow_select::filter::filter_record_batch;
+use arrow_select::take::take_record_batch;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+// Arrow IPC files start with this magic; anything else is read as a stream.
+pub const MAGIC: &[u8; 6] = b"ARROW1";
+
+// Points read from Arrow IPC: payload holds every column other than the
+// coordinates, its row i belonging to points[i].
+pub struct ArrowPoints {
+    pub points: Vec<Point>,
+    pub payload: RecordBatch,
+    // Input rows, numbered from 0, with null or non-finite coordinates.
+    pub skipped: Vec<usize>,
+}
+
+fn coordinates(batch: &RecordBatch, column: usize) -> Result<Vec<Option<f64>>, ArrowError> {
+    let array = batch.column(column);
+    match array.data_type() {
+        DataType::Float64 => Ok(array.as_primitive::<Float64Type>().iter().collect()),
+        DataType::Float32 => Ok(array.as_primitive::<Float32Type>().iter().map(|v| v.map(f64::from)).collect()),
+        other => Err(ArrowError::SchemaError(format!("expected a float column for coordinates, found {}", other))),
+    }
+}
+
+fn read_batches<R: Read + Seek>(mut reader: R) -> Result<(SchemaRef, Vec<RecordBatch>), ArrowError> {
+    let mut magic = [0_u8; 6];
+    let is_file = reader.read_exact(&mut magic).is_ok() && &magic == MAGIC;
+    reader.seek(SeekFrom::Start(0))?;
+    if is_file {
+        let reader = FileReader::try_new(reader, None)?;
+        let schema = reader.schema();
+        Ok((schema, reader.collect::<Result<Vec<RecordBatch>, ArrowError>>()?))
+    } else {
+        let reader = StreamReader::try_new(reader, None)?;
+        let schema = reader.schema();
+        Ok((schema, reader.collect::<Result<Vec<RecordBatch>, ArrowError>>()?))
+    }
+}
+
+impl ArrowPoints {
+    pub fn load(path: &str, x_column: &str, y_column: &str) -> Result<ArrowPoints, ArrowError> {
+        ArrowPoints::read(BufReader::new(File::open(path)?), x_column, y_column)
+    }
+
+    // Reads the IPC file or stream format, selecting coordinates by name.
+    pub fn read<R: Read + Seek>(reader: R, x_column: &str, y_column: &str) -> Result<ArrowPoints, ArrowError> {
+        let (schema, batches) = read_batches(reader)?;
+        let x = schema.index_of(x_column)?;
+        let y = schema.index_of(y_column)?;
+        ArrowPoints::from_batches(&schema, &batches, x, y)
+    }
+
+    // Same as read, selecting coordinates by position as the text loader does.
+    pub fn read_columns<R: Read + Seek>(reader: R, x_column: usize, y_column: usize) -> Result<ArrowPoints, ArrowError> {
+        let (schema, batches) = read_batches(reader)?;
+        for column in [x_column, y_column].iter() {
+            if *column >= schema.fields().len() {
+                return Err(ArrowError::SchemaError(format!("expected at least {} columns, found {}", column + 1, schema.fields().len())));
+            }
+        }
+        ArrowPoints::from_batches(&schema, &batches, x_column, y_column)
+    }
+
+    pub fn from_batches(schema: &SchemaRef, batches: &[RecordBatch], x_column: usize, y_column: usize) -> Result<ArrowPoints, ArrowError> {
+        let batch = concat_batches(schema, batches)?;
+        let xs = coordinates(&batch, x_column)?;
+        let ys = coordinates(&batch, y_column)?;
+        let mut points: Vec<Point> = Vec::with_capacity(batch.num_rows());
+        let mut skipped: Vec<usize> = Vec::new();
+        let mut valid: Vec<bool> = Vec::with_capacity(batch.num_rows());
+        for (row, (x, y)) in xs.into_iter().zip(ys).enumerate() {
+            match (x, y) {
+                (Some(x), Some(y)) if x.is_finite() && y.is_finite() => {
+                    points.push(Point { x, y });
+                    valid.push(true);
+                }
+                _ => {
+                    skipped.push(row);
+                    valid.push(false);
+                }
+            }
+        }
+        let payload_columns: Vec<usize> = (0..schema.fields().len()).filter(|i| *i != x_column && *i != y_column).collect();
+        let mut payload = batch.project(&payload_columns)?;
+        if !skipped.is_empty() {
+            payload = filter_record_batch(&payload, &BooleanArray::from(valid))?;
+        }
+        Ok(ArrowPoints {
+            points,
+            payload,
+            skipped,
+        })
+    }
+
+    // Rows for the given point positions, e.g. ids returned by a sampler,
+    // as x and y columns followed by the payload.
+    pub fn take(&self, ids: &[usize]) -> Result<RecordBatch, ArrowError> {
+        let indices = UInt64Array::from(ids.iter().map(|id| *id as u64).collect::<Vec<u64>>());
+        let payload = take_record_batch(&self.payload, &indices)?;
+        let samples: Vec<Point> = ids.iter().map(|id| self.points[*id].clone()).collect();
+        let coordinates = points_to_batch(&samples);
+        let mut fields: Vec<Field> = coordinates.schema().fields().iter().map(|field| field.as_ref().clone()).collect();
+        fields.extend(payload.schema().fields().iter().map(|field| field.as_ref().clone()));
+        let mut columns: Vec<ArrayRef> = coordinates.columns().to_vec();
+        columns.extend_from_slice(payload.columns());
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+}
+
+pub fn points_to_batch(points: &[Point]) -> RecordBatch {
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Float64, false),
+        Field::new("y", DataType::Float64, false),
+    ]);
+    let x: ArrayRef = Arc::new(Float64Array::from(points.iter().map(|p| p.x).collect::<Vec<f64>>()));
+    let y: ArrayRef = Arc::new(Float64Array::from(points.iter().map(|p| p.y).collect::<Vec<f64>>()));
+    RecordBatch::try_new(Arc::new(schema), vec![x, y]).unwrap()
+}
+
+pub fn save_batch(path: &str, batch: &RecordBatch) -> Result<(), ArrowError> {
+    write_batch(BufWriter::new(File::create(path)?), batch)
+}
+
+// Writes the batch in the IPC file format.
+pub fn write_batch<W: Write>(writer: W, batch: &RecordBatch) -> Result<(), ArrowError> {
+    let mut writer = FileWriter::try_new(writer, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::PointLoader;
+    use arrow_ipc::writer::StreamWriter;
+    use std::io::Cursor;
+
+    fn batch() -> RecordBatch {
+        let points = points_to_batch(&[Point::new(1.0, 2.0), Point::new(f64::NAN, 0.0), Point::new(-3.0, 4.5)]);
+        let ids: ArrayRef = Arc::new(UInt64Array::from(vec![10, 11, 12]));
+        let mut fields: Vec<Field> = points.schema().fields().iter().map(|field| field.as_ref().clone()).collect();
+        fields.push(Field::new("id", DataType::UInt64, false));
+        let mut columns: Vec<ArrayRef> = points.columns().to_vec();
+        columns.push(ids);
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    }
+
+    fn stream(batch: &RecordBatch) -> Vec<u8> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut buffer, &batch.schema()).unwrap();
+        writer.write(batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        buffer
+    }
+
+    #[test]
+    fn reads_files_and_streams() {
+        let mut file: Vec<u8> = Vec::new();
+        write_batch(&mut file, &batch()).unwrap();
+        for bytes in [file, stream(&batch())].iter() {
+            let arrow = ArrowPoints::read(Cursor::new(bytes), "x", "y").unwrap();
+            assert_eq!(arrow.points, vec![Point::new(1.0, 2.0), Point::new(-3.0, 4.5)]);
+            assert_eq!(arrow.skipped, vec![1]);
+            let rows = arrow.take(&[1]).unwrap();
+            assert_eq!(rows.column(2).as_primitive::<arrow_array::types::UInt64Type>().value(0), 12);
+        }
+    }
+
+    #[test]
+    fn loader_recognizes_streams() {
+        let path = std::env::temp_dir().join(format!("range-sampling-stream-{}.arrows", std::process::id()));
+        std::fs::write(&path, stream(&batch())).unwrap();
+        let result = PointLoader::new().load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let result = result.unwrap();
+        assert_eq!(result.points, vec![Point::new(1.0, 2.0), Point::new(-3.0, 4.5)]);
+        assert_eq!(result.bad_lines.len(), 1);
+        assert_eq!(result.bad_lines[0].line, 2);
+    }
+}
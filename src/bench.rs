@@ -0,0 +1,109 @@
+use serde_json::json;
+use std::io::Write;
+use std::time::Instant;
+
+// Latencies of one method, k and workload, in microseconds.
+#[derive(PartialEq, Clone, Debug)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct BenchResult {
+    pub method: String,
+    pub k: usize,
+    // Average number of points in the queries of the workload.
+    pub selectivity: f64,
+    pub queries: usize,
+    pub warmup: usize,
+    pub repetitions: usize,
+    // Seconds.
+    pub build_time: f64,
+    pub index_size: usize,
+    pub latency: LatencySummary,
+}
+
+const CSV_HEADER: &str = "method,k,selectivity,queries,warmup,repetitions,build_time_s,index_size,mean_us,p50_us,p90_us,p99_us,max_us";
+
+impl LatencySummary {
+    // Nearest-rank percentiles; all zero without latencies.
+    pub fn from(latencies: &[f64]) -> LatencySummary {
+        if latencies.is_empty() {
+            return LatencySummary { count: 0, mean: 0.0, p50: 0.0, p90: 0.0, p99: 0.0, max: 0.0 };
+        }
+        let mut sorted = latencies.to_vec();
+        sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+        LatencySummary {
+            count: sorted.len(),
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+// Runs `query` on every query index `warmup` times untimed, then
+// `repetitions` times timed, and returns one latency per timed run.
+pub fn measure(queries: usize, warmup: usize, repetitions: usize, query: &mut dyn FnMut(usize)) -> Vec<f64> {
+    for _ in 0..warmup {
+        for i in 0..queries {
+            query(i);
+        }
+    }
+    let mut latencies: Vec<f64> = Vec::with_capacity(queries * repetitions);
+    for _ in 0..repetitions {
+        for i in 0..queries {
+            let now = Instant::now();
+            query(i);
+            latencies.push(now.elapsed().as_nanos() as f64 / 1000.0);
+        }
+    }
+    latencies
+}
+
+pub fn write_csv<W: Write>(mut writer: W, results: &[BenchResult]) -> std::io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for r in results.iter() {
+        writeln!(writer, "{},{},{},{},{},{},{},{},{},{},{},{},{}", r.method, r.k, r.selectivity, r.queries, r.warmup,
+            r.repetitions, r.build_time, r.index_size, r.latency.mean, r.latency.p50, r.latency.p90, r.latency.p99, r.latency.max)?;
+    }
+    writer.flush()
+}
+
+pub fn write_json<W: Write>(mut writer: W, results: &[BenchResult]) -> std::io::Result<()> {
+    let results: Vec<serde_json::Value> = results.iter().map(|r| json!({
+        "method": r.method,
+        "k": r.k,
+        "selectivity": r.selectivity,
+        "queries": r.queries,
+        "warmup": r.warmup,
+        "repetitions": r.repetitions,
+        "build_time_s": r.build_time,
+        "index_size": r.index_size,
+        "latency_us": {
+            "count": r.latency.count,
+            "mean": r.latency.mean,
+            "p50": r.latency.p50,
+            "p90": r.latency.p90,
+            "p99": r.latency.p99,
+            "max": r.latency.max,
+        },
+    })).collect();
+    serde_json::to_writer_pretty(&mut writer, &results)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+// JSON for paths ending in .json, CSV otherwise.
+pub fn save(path: &str, results: &[BenchResult]) -> std::io::Result<()> {
+    let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    if path.ends_with(".json") { write_json(writer, results) } else { write_csv(writer, results) }
+}
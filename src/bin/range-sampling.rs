@@ -0,0 +1,578 @@
+extern crate range_sampling;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+use std::str::FromStr;
+use std::time::Instant;
+use range_sampling::geo::{Geometry, MBR, Point};
+use range_sampling::geojson::{self, FeatureCollection};
+use range_sampling::index::named::{INDEX_NAMES, NamedIndex};
+use range_sampling::loader::{self, PointLoader, TextFormat};
+use range_sampling::pointfile::PointFile;
+use range_sampling::util::SampleQuery;
+use range_sampling::workload::{self, CenterDistribution, WorkloadGenerator};
+use range_sampling::bench::{self, BenchResult, LatencySummary};
+
+const USAGE: &str = "Usage: range-sampling <command> [flags]
+
+Commands:
+  build     build indexes and report build time and size
+  sample    draw k samples from every query
+  count     count the points of every query
+  range     report the points of every query
+  bench     time sampling queries
+  convert   convert a point file between formats
+  workload  generate query rectangles from the data
+
+Input flags:
+  --data <file>             points as text, binary point file, GeoJSON or Arrow
+  --columns <x>,<y>         text columns of the coordinates, from 0 (default 0,1)
+  --delimiter <c>           text column delimiter (default whitespace)
+  --skip-lines <n>          header lines of the data to skip
+  --geometry                text lines are WKT or hex WKB points
+
+Query flags (one of --queries, --ranges, --query, --shapes):
+  --queries <file>          query centers, one point per line, in the text
+                            layout given by --columns, --delimiter and
+                            --geometry
+  --area <a>                area of the queries around the centers
+  --ratio <r>               height to width ratio of those queries (default 1)
+  --ranges <file>           query rectangles, \"x1 y1 x2 y2\" per line
+  --query <x1>,<y1>,<x2>,<y2>
+                            a single query rectangle, by any two opposite
+                            corners
+  --shapes <file>           WKT or hex WKB query geometries, one per line
+
+Other flags:
+  --index <name>[,<name>]   index and sampling method (default kd); build
+                            accepts \"all\"
+  -k, --k <n>               samples per query; bench takes a list like 10,100
+  --output <file>           write points to a file instead of stdout; bench
+                            writes results as CSV, or JSON for .json files
+  --warmup <n>              bench: untimed passes over the queries (default 0)
+  --repetitions <n>         bench: timed passes over the queries (default 1)
+  --to <format>             text, wkt, binary, geojson or arrow (default from
+                            the --output extension)
+
+Workload flags:
+  --count <n>               number of queries (default 100)
+  --centers <dist>          data or uniform (default data)
+  --selectivity <n>         fit every query to about n points (default 1000)
+  --area <a>                use a fixed query area instead
+  --aspect <min>,<max>      range of height to width ratios (default 1,1)
+  --hotspots <n>            cluster centers around n hotspots
+  --skew <s>                Zipf exponent of hotspot popularity (default 1)
+  --spread <f>              hotspot size as a fraction of the data extent
+                            (default 0.01)
+  --seed <n>                random seed (default 0)";
+
+const INPUT_FLAGS: &[&str] = &["data", "columns", "delimiter", "skip-lines", "geometry"];
+const QUERY_FLAGS: &[&str] = &["queries", "area", "ratio", "ranges", "query", "shapes"];
+const SWITCHES: &[&str] = &["geometry"];
+
+enum Query {
+    Rect(MBR),
+    Shape(Geometry, MBR),
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum OutputFormat {
+    Text,
+    Wkt,
+    Binary,
+    GeoJson,
+    Arrow,
+}
+
+struct Flags {
+    values: HashMap<String, String>,
+}
+
+impl Flags {
+    fn parse(command: &str, allowed: &[&str], args: &[String]) -> Result<Flags, String> {
+        let mut values: HashMap<String, String> = HashMap::new();
+        let mut i = 0;
+        while i < args.len() {
+            let arg = &args[i];
+            let (name, inline_value) = if arg == "-k" {
+                ("k".to_string(), None)
+            } else if let Some(flag) = arg.strip_prefix("--") {
+                match flag.find('=') {
+                    Some(pos) => (flag[..pos].to_string(), Some(flag[pos + 1..].to_string())),
+                    None => (flag.to_string(), None),
+                }
+            } else {
+                return Err(format!("unexpected argument '{}'; flags start with --", arg));
+            };
+            if !allowed.contains(&name.as_str()) {
+                return Err(format!("unknown flag --{} for {}; expected one of {}", name, command,
+                    allowed.iter().map(|flag| format!("--{}", flag)).collect::<Vec<String>>().join(", ")));
+            }
+            let value = if SWITCHES.contains(&name.as_str()) {
+                if inline_value.is_some() {
+                    return Err(format!("--{} takes no value", name));
+                }
+                String::new()
+            } else if let Some(value) = inline_value {
+                value
+            } else {
+                i += 1;
+                args.get(i).cloned().ok_or_else(|| format!("missing value for --{}", name))?
+            };
+            if values.insert(name.clone(), value).is_some() {
+                return Err(format!("--{} given more than once", name));
+            }
+            i += 1;
+        }
+        Ok(Flags { values })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.as_str())
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.get(name).ok_or_else(|| format!("missing required flag --{}", name))
+    }
+
+    fn parse_value<T: FromStr>(&self, name: &str, expected: &str) -> Result<Option<T>, String> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(value) => value.parse::<T>().map(Some)
+                .map_err(|_| format!("invalid value '{}' for --{}, expected {}", value, name, expected)),
+        }
+    }
+
+    fn positive(&self, name: &str) -> Result<Option<f64>, String> {
+        match self.parse_value::<f64>(name, "a positive number")? {
+            Some(value) if !(value > 0.0 && value.is_finite()) =>
+                Err(format!("invalid value '{}' for --{}, expected a positive number", value, name)),
+            value => Ok(value),
+        }
+    }
+
+    fn k(&self) -> Result<usize, String> {
+        match self.parse_value::<usize>("k", "a positive integer")? {
+            None => Err("missing required flag --k".to_string()),
+            Some(0) => Err("invalid value '0' for --k, expected a positive integer".to_string()),
+            Some(k) => Ok(k),
+        }
+    }
+}
+
+fn parse_list<T: FromStr>(value: &str, name: &str, len: usize, expected: &str) -> Result<Vec<T>, String> {
+    let items: Result<Vec<T>, _> = value.split(',').map(|item| item.trim().parse::<T>()).collect();
+    match items {
+        Ok(items) if items.len() == len => Ok(items),
+        _ => Err(format!("invalid value '{}' for --{}, expected {}", value, name, expected)),
+    }
+}
+
+// Layout of text lines, shared by the data and query center files.
+fn text_loader(flags: &Flags) -> Result<PointLoader, String> {
+    let mut point_loader = PointLoader::new();
+    if let Some(columns) = flags.get("columns") {
+        let columns = parse_list::<usize>(columns, "columns", 2, "two column numbers like 0,1")?;
+        point_loader = point_loader.columns(columns[0], columns[1]);
+    }
+    if let Some(delimiter) = flags.get("delimiter") {
+        let mut chars = delimiter.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => point_loader = point_loader.delimiter(c),
+            _ => return Err(format!("invalid value '{}' for --delimiter, expected a single character", delimiter)),
+        }
+    }
+    if flags.has("geometry") {
+        point_loader = point_loader.format(TextFormat::Geometry);
+    }
+    Ok(point_loader)
+}
+
+fn load_data(flags: &Flags) -> Result<Vec<Point>, String> {
+    let path = flags.required("data")?;
+    if path.ends_with(".geojson") || path.ends_with(".json") {
+        let collection = FeatureCollection::load(path).map_err(|err| format!("{}: {}", path, err))?;
+        for skipped in collection.skipped.iter() {
+            eprintln!("{}: skipped {}", path, skipped);
+        }
+        return Ok(collection.points);
+    }
+    let mut point_loader = text_loader(flags)?;
+    if let Some(skip_lines) = flags.parse_value::<usize>("skip-lines", "a number of lines")? {
+        point_loader = point_loader.skip_lines(skip_lines);
+    }
+    let points = point_loader.load(path).map_err(|err| format!("{}: {}", path, err))?.report(path);
+    if points.is_empty() {
+        return Err(format!("{}: no points", path));
+    }
+    Ok(points)
+}
+
+fn load_queries(flags: &Flags, k: usize) -> Result<Vec<Query>, String> {
+    let given: Vec<&&str> = ["queries", "ranges", "query", "shapes"].iter().filter(|name| flags.has(name)).collect();
+    if given.len() != 1 {
+        return Err("expected exactly one of --queries, --ranges, --query and --shapes".to_string());
+    }
+    if let Some(path) = flags.get("queries") {
+        let area = flags.positive("area")?.ok_or("--queries needs --area")?;
+        let ratio = flags.positive("ratio")?.unwrap_or(1.0);
+        let centers = text_loader(flags)?.load(path).map_err(|err| format!("{}: {}", path, err))?.report(path);
+        Ok(centers.iter().map(|p| Query::Rect(SampleQuery::from(p, area, ratio, k).range)).collect())
+    } else if let Some(path) = flags.get("ranges") {
+        let (ranges, bad_lines) = loader::load_ranges(path).map_err(|err| format!("{}: {}", path, err))?;
+        for bad_line in bad_lines.iter() {
+            eprintln!("{}: skipped {}", path, bad_line);
+        }
+        Ok(ranges.into_iter().map(Query::Rect).collect())
+    } else if let Some(query) = flags.get("query") {
+        let v = parse_list::<f64>(query, "query", 4, "four numbers x1,y1,x2,y2")?;
+        // Either pair of opposite corners, as in --ranges files.
+        Ok(vec![Query::Rect(MBR::from_points(&[Point::new(v[0], v[1]), Point::new(v[2], v[3])]))])
+    } else {
+        let path = flags.required("shapes")?;
+        let (geometries, bad_lines) = loader::load_geometries(path).map_err(|err| format!("{}: {}", path, err))?;
+        for bad_line in bad_lines.iter() {
+            eprintln!("{}: skipped {}", path, bad_line);
+        }
+        Ok(geometries.into_iter().map(|geometry| {
+            let bounding_box = geometry.bounding_box();
+            Query::Shape(geometry, bounding_box)
+        }).collect())
+    }
+}
+
+fn build_index(name: &str, data: &[Point]) -> Result<(NamedIndex, f64), String> {
+    let now = Instant::now();
+    let index = NamedIndex::from_name(name, data)
+        .ok_or_else(|| format!("unknown index '{}', expected one of {}", name, INDEX_NAMES.join(", ")))?;
+    Ok((index, now.elapsed().as_micros() as f64 / 1000000.0_f64))
+}
+
+// Checks the names before anything is built, so that typos fail fast.
+fn index_names(flags: &Flags, allow_all: bool) -> Result<Vec<String>, String> {
+    let value = flags.get("index").unwrap_or("kd");
+    if allow_all && value == "all" {
+        return Ok(INDEX_NAMES.iter().map(|name| name.to_string()).collect());
+    }
+    let names: Vec<String> = value.split(',').map(|name| name.trim().to_string()).collect();
+    for name in names.iter() {
+        if !INDEX_NAMES.contains(&name.as_str()) {
+            return Err(format!("unknown index '{}', expected one of {}", name, INDEX_NAMES.join(", ")));
+        }
+    }
+    Ok(names)
+}
+
+fn single_index(flags: &Flags) -> Result<String, String> {
+    let mut names = index_names(flags, false)?;
+    if names.len() != 1 {
+        return Err("expected a single --index".to_string());
+    }
+    Ok(names.remove(0))
+}
+
+fn check_shapes(index: &NamedIndex, queries: &[Query]) -> Result<(), String> {
+    if queries.iter().any(|query| matches!(query, Query::Shape(..))) && !index.supports_regions() {
+        return Err(format!("index '{}' cannot sample query shapes; use kd, rs or zv", index.name()));
+    }
+    Ok(())
+}
+
+fn query_range(index: &NamedIndex, query: &Query) -> Vec<Point> {
+    match query {
+        Query::Rect(range) => index.range(range),
+        Query::Shape(geometry, bounding_box) => index.region_range(geometry, bounding_box),
+    }
+}
+
+fn query_count(index: &NamedIndex, query: &Query) -> usize {
+    match query {
+        Query::Rect(range) => index.range_count(range),
+        Query::Shape(geometry, bounding_box) => index.region_range(geometry, bounding_box).len(),
+    }
+}
+
+fn query_sampling(index: &mut NamedIndex, query: &Query, k: usize) -> Vec<Point> {
+    match query {
+        Query::Rect(range) => index.range_sampling(range, k),
+        Query::Shape(geometry, _) => index.region_range_sampling(geometry, k).unwrap(),
+    }
+}
+
+fn query_to_string(query: &Query) -> String {
+    match query {
+        Query::Rect(range) => format!("{} {} {} {}", range.low.x, range.low.y, range.high.x, range.high.y),
+        Query::Shape(geometry, _) => geometry.to_wkt(),
+    }
+}
+
+fn output_format(flags: &Flags) -> Result<OutputFormat, String> {
+    if let Some(to) = flags.get("to") {
+        return match to {
+            "text" => Ok(OutputFormat::Text),
+            "wkt" => Ok(OutputFormat::Wkt),
+            "binary" => Ok(OutputFormat::Binary),
+            "geojson" => Ok(OutputFormat::GeoJson),
+            "arrow" => Ok(OutputFormat::Arrow),
+            other => Err(format!("invalid value '{}' for --to, expected text, wkt, binary, geojson or arrow", other)),
+        };
+    }
+    let path = flags.get("output").unwrap_or("");
+    Ok(if path.ends_with(".bin") || path.ends_with(".rspt") {
+        OutputFormat::Binary
+    } else if path.ends_with(".geojson") || path.ends_with(".json") {
+        OutputFormat::GeoJson
+    } else if path.ends_with(".arrow") || path.ends_with(".ipc") {
+        OutputFormat::Arrow
+    } else if path.ends_with(".wkt") {
+        OutputFormat::Wkt
+    } else {
+        OutputFormat::Text
+    })
+}
+
+fn write_text<W: Write>(mut writer: W, points: &[Point], format: OutputFormat) -> std::io::Result<()> {
+    for p in points.iter() {
+        if format == OutputFormat::Wkt {
+            writeln!(writer, "{}", Geometry::Point(p.clone()).to_wkt())?;
+        } else {
+            writeln!(writer, "{} {}", p.x, p.y)?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(feature = "arrow")]
+fn write_arrow(path: &str, points: &[Point]) -> Result<(), String> {
+    range_sampling::arrow::save_batch(path, &range_sampling::arrow::points_to_batch(points)).map_err(|err| format!("{}: {}", path, err))
+}
+
+#[cfg(not(feature = "arrow"))]
+fn write_arrow(_path: &str, _points: &[Point]) -> Result<(), String> {
+    Err("arrow output needs range-sampling built with the arrow feature".to_string())
+}
+
+// Writes to --output, or as text to stdout.
+fn write_points(flags: &Flags, points: &[Point]) -> Result<(), String> {
+    let format = output_format(flags)?;
+    let path = match flags.get("output") {
+        Some(path) => path,
+        None => {
+            if format != OutputFormat::Text && format != OutputFormat::Wkt {
+                return Err("--to binary, geojson and arrow need --output".to_string());
+            }
+            let stdout = std::io::stdout();
+            return write_text(stdout.lock(), points, format).map_err(|err| err.to_string());
+        }
+    };
+    let res = match format {
+        OutputFormat::Text | OutputFormat::Wkt => File::create(path).and_then(|file| write_text(BufWriter::new(file), points, format)),
+        OutputFormat::Binary => PointFile::save(path, points, None, None),
+        OutputFormat::GeoJson => File::create(path).and_then(|file| geojson::write_points(BufWriter::new(file), points)),
+        OutputFormat::Arrow => return write_arrow(path, points),
+    };
+    res.map_err(|err| format!("{}: {}", path, err))
+}
+
+fn build(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse("build", &[INPUT_FLAGS, &["index"]].concat(), args)?;
+    let names = index_names(&flags, true)?;
+    let data = load_data(&flags)?;
+    for name in names.iter() {
+        let (index, build_time) = build_index(name, &data)?;
+        println!("{}: built in {} s, index size = {}", name, build_time, index.size());
+    }
+    Ok(())
+}
+
+fn sample(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse("sample", &[INPUT_FLAGS, QUERY_FLAGS, &["index", "k", "output", "to"]].concat(), args)?;
+    let name = single_index(&flags)?;
+    let k = flags.k()?;
+    output_format(&flags)?;
+    let data = load_data(&flags)?;
+    let queries = load_queries(&flags, k)?;
+    let (mut index, _) = build_index(&name, &data)?;
+    check_shapes(&index, &queries)?;
+    let mut samples: Vec<Point> = Vec::new();
+    for (i, query) in queries.iter().enumerate() {
+        // Empty queries have no samples, which is worth a note.
+        let query_samples = query_sampling(&mut index, query, k);
+        if query_samples.is_empty() {
+            eprintln!("query {} is empty, skipped", i + 1);
+        }
+        samples.extend(query_samples);
+    }
+    write_points(&flags, &samples)
+}
+
+fn count(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse("count", &[INPUT_FLAGS, QUERY_FLAGS, &["index"]].concat(), args)?;
+    let name = single_index(&flags)?;
+    let data = load_data(&flags)?;
+    let queries = load_queries(&flags, 1)?;
+    let (index, _) = build_index(&name, &data)?;
+    for query in queries.iter() {
+        println!("{} {}", query_to_string(query), query_count(&index, query));
+    }
+    Ok(())
+}
+
+fn range(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse("range", &[INPUT_FLAGS, QUERY_FLAGS, &["index", "output", "to"]].concat(), args)?;
+    let name = single_index(&flags)?;
+    output_format(&flags)?;
+    let data = load_data(&flags)?;
+    let queries = load_queries(&flags, 1)?;
+    let (index, _) = build_index(&name, &data)?;
+    let mut points: Vec<Point> = Vec::new();
+    for query in queries.iter() {
+        points.extend(query_range(&index, query));
+    }
+    write_points(&flags, &points)
+}
+
+fn bench(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse("bench", &[INPUT_FLAGS, QUERY_FLAGS, &["index", "k", "warmup", "repetitions", "output"]].concat(), args)?;
+    let names = index_names(&flags, false)?;
+    let k_list = flags.required("k")?;
+    let k_values: Vec<usize> = k_list.split(',').map(|k| k.trim().parse::<usize>()).collect::<Result<Vec<usize>, _>>().ok()
+        .filter(|k_values| !k_values.contains(&0))
+        .ok_or_else(|| format!("invalid value '{}' for --k, expected positive integers like 10,100", k_list))?;
+    let warmup = flags.parse_value::<usize>("warmup", "a number of passes")?.unwrap_or(0);
+    let repetitions = flags.parse_value::<usize>("repetitions", "a positive number of passes")?.unwrap_or(1);
+    if repetitions == 0 {
+        return Err("invalid value '0' for --repetitions, expected a positive number of passes".to_string());
+    }
+    let data = load_data(&flags)?;
+    let queries = load_queries(&flags, 1)?;
+    let mut results: Vec<BenchResult> = Vec::new();
+    for name in names.iter() {
+        let (mut index, build_time) = build_index(name, &data)?;
+        check_shapes(&index, &queries)?;
+        println!("{}: built in {} s, index size = {}", name, build_time, index.size());
+        // Samplers give up on empty queries after a while, which would skew
+        // the latencies, so they are skipped.
+        let counts: Vec<usize> = queries.iter().map(|query| query_count(&index, query)).collect();
+        let selectivity = counts.iter().sum::<usize>() as f64 / queries.len().max(1) as f64;
+        let non_empty: Vec<&Query> = queries.iter().zip(counts.iter()).filter(|(_, count)| **count > 0).map(|(query, _)| query).collect();
+        for k in k_values.iter() {
+            let k = *k;
+            let latencies = bench::measure(non_empty.len(), warmup, repetitions, &mut |i| {
+                let samples = query_sampling(&mut index, non_empty[i], k);
+                assert_eq!(samples.len(), k);
+            });
+            let latency = LatencySummary::from(&latencies);
+            println!("{}: k = {}, {} queries ({} empty skipped), avg range size = {}, latency us: mean {:.3}, p50 {:.3}, p90 {:.3}, p99 {:.3}, max {:.3}",
+                name, k, non_empty.len(), queries.len() - non_empty.len(), selectivity, latency.mean, latency.p50, latency.p90, latency.p99, latency.max);
+            results.push(BenchResult {
+                method: name.clone(),
+                k,
+                selectivity,
+                queries: non_empty.len(),
+                warmup,
+                repetitions,
+                build_time,
+                index_size: index.size(),
+                latency,
+            });
+        }
+    }
+    if let Some(path) = flags.get("output") {
+        bench::save(path, &results).map_err(|err| format!("{}: {}", path, err))?;
+    }
+    Ok(())
+}
+
+fn convert(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse("convert", &[INPUT_FLAGS, &["output", "to"]].concat(), args)?;
+    flags.required("output")?;
+    output_format(&flags)?;
+    let data = load_data(&flags)?;
+    write_points(&flags, &data)?;
+    eprintln!("wrote {} points to {}", data.len(), flags.required("output")?);
+    Ok(())
+}
+
+fn workload(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse("workload", &[INPUT_FLAGS, &["count", "centers", "selectivity", "area", "aspect",
+        "hotspots", "skew", "spread", "seed", "output"]].concat(), args)?;
+    let mut generator = WorkloadGenerator::new();
+    generator = match flags.get("centers") {
+        None | Some("data") => generator.centers(CenterDistribution::Data),
+        Some("uniform") => generator.centers(CenterDistribution::Uniform),
+        Some(other) => return Err(format!("invalid value '{}' for --centers, expected data or uniform", other)),
+    };
+    if flags.has("selectivity") && flags.has("area") {
+        return Err("expected at most one of --selectivity and --area".to_string());
+    }
+    if let Some(area) = flags.positive("area")? {
+        generator = generator.area(area);
+    }
+    match flags.parse_value::<usize>("selectivity", "a positive integer")? {
+        Some(0) => return Err("invalid value '0' for --selectivity, expected a positive integer".to_string()),
+        Some(selectivity) => generator = generator.selectivity(selectivity),
+        None => {}
+    }
+    if let Some(aspect) = flags.get("aspect") {
+        let v = if aspect.contains(',') {
+            parse_list::<f64>(aspect, "aspect", 2, "a ratio or min,max ratios")?
+        } else {
+            parse_list::<f64>(aspect, "aspect", 1, "a ratio or min,max ratios")?.repeat(2)
+        };
+        if !(v[0] > 0.0 && v[0] <= v[1] && v[1].is_finite()) {
+            return Err(format!("invalid value '{}' for --aspect, expected positive ratios with min <= max", aspect));
+        }
+        generator = generator.aspect_ratio(v[0], v[1]);
+    }
+    if let Some(hotspots) = flags.parse_value::<usize>("hotspots", "a number of hotspots")? {
+        let skew = flags.parse_value::<f64>("skew", "a non-negative number")?.unwrap_or(1.0);
+        generator = generator.hotspots(hotspots, skew);
+    } else if flags.has("skew") || flags.has("spread") {
+        return Err("--skew and --spread need --hotspots".to_string());
+    }
+    if let Some(spread) = flags.parse_value::<f64>("spread", "a non-negative fraction")? {
+        generator = generator.hotspot_spread(spread);
+    }
+    if let Some(seed) = flags.parse_value::<u64>("seed", "a non-negative integer")? {
+        generator = generator.seed(seed);
+    }
+    let count = flags.parse_value::<usize>("count", "a number of queries")?.unwrap_or(100);
+    let data = load_data(&flags)?;
+    let queries = generator.generate(&data, count).map_err(|err| err.to_string())?;
+    match flags.get("output") {
+        Some(path) => File::create(path).and_then(|file| workload::write_queries(BufWriter::new(file), &queries))
+            .map_err(|err| format!("{}: {}", path, err)),
+        None => workload::write_queries(std::io::stdout().lock(), &queries).map_err(|err| err.to_string()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || ["help", "--help", "-h"].contains(&args[1].as_str()) || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let res = match args[1].as_str() {
+        "build" => build(&args[2..]),
+        "sample" => sample(&args[2..]),
+        "count" => count(&args[2..]),
+        "range" => range(&args[2..]),
+        "bench" => bench(&args[2..]),
+        "convert" => convert(&args[2..]),
+        "workload" => workload(&args[2..]),
+        other => Err(format!("unknown command '{}', expected one of build, sample, count, range, bench, convert, workload", other)),
+    };
+    if let Err(err) = res {
+        eprintln!("range-sampling: {}", err);
+        eprintln!("Run 'range-sampling --help' for usage.");
+        process::exit(2);
+    }
+}
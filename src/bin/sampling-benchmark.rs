@@ -1,18 +1,25 @@
 extern crate range_sampling;
 
 use std::fs::File;
-use std::io::BufReader;
 use std::io::prelude::*;
 use std::env;
 use std::process;
 use std::time::Instant;
-use range_sampling::geo::Point;
+use range_sampling::geo::{MBR, Point};
 use range_sampling::index::kdtree::KDTree;
-use range_sampling::index::kdbtree::KDBTree;
-use range_sampling::index::zvtree::ZVTree;
-use range_sampling::index::rstree::RSTree;
-use range_sampling::index::rsbtree::RSBTree;
-use range_sampling::util::{Config, sample_from};
+use range_sampling::index::zvtree::DEFAULT_BIGMIN_MAX_INTERVALS;
+use range_sampling::index::named::NamedIndex;
+use range_sampling::util::Config;
+use range_sampling::loader::{self, PointLoader};
+use range_sampling::bench::{self, BenchResult, LatencySummary};
+
+// Method codes of the config and the index each of them runs on.
+const METHODS: &[(&str, &str)] = &[
+    ("qts", "query-then-sample"), ("kds", "kd"), ("kdo", "kd-olken"), ("kdb", "kdb"),
+    ("zvs", "zv"), ("zvb", "zv"), ("hvs", "hv"), ("rts", "rs"), ("rto", "rs-olken"),
+    ("rtb", "rsb"), ("pqs", "quad"), ("pqo", "quad-olken"), ("gds", "grid"),
+    ("lzs", "learned"), ("rgs", "range-tree"),
+];
 
 fn main() -> std::io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -24,187 +31,91 @@ fn main() -> std::io::Result<()> {
     let mut config_json = String::new();
     config_file.read_to_string(&mut config_json)?;
     let config: Config = serde_json::from_str(&config_json).expect("Config format error");
+    let repetitions = config.repetitions.unwrap_or(1);
 
-    let mut data: Vec<Point> = Vec::new();
-    {
-        let input_file = File::open(config.input_file)?;
-        let mut buf_reader = BufReader::new(&input_file);
-        let mut buf: String = String::new();
-        loop {
-            buf.clear();
-            let read_len = buf_reader.read_line(&mut buf)?;
-            if read_len == 0 {
-                break;
-            } else {
-                let nums: Vec<&str> = buf[0..buf.len() - 1].split(' ').collect();
-                assert_eq!(nums.len(), 3);
-                let x = nums[0].parse::<f64>().expect("Expect to be f64");
-                let y = nums[1].parse::<f64>().expect("Expect to be f64");
-                data.push(Point{x, y});
-            }
-        }
-    }
-
-    let mut qts = false; let mut kds = false; let mut kdo = false;
-    let mut kdb = false; let mut zvs = false; let mut rts = false;
-    let mut rtb = false; let mut rto = false;
-    for method in config.methods.iter() {
-        if method == "qts" { qts = true; }
-        else if method == "kds" { kds = true; }
-        else if method == "kdo" { kdo = true; }
-        else if method == "kdb" { kdb = true; }
-        else if method == "zvs" { zvs = true; }
-        else if method == "rts" { rts = true; }
-        else if method == "rtb" { rtb = true; }
-        else if method == "rto" { rto = true; }
-    }
-    println!("Start building sampling indexes....");
-    let now = Instant::now(); 
-    let kdtree = KDTree::from(&data);
-    println!("Finish buildnig KD-Tree, takes {} s", now.elapsed().as_micros() as f64 / 1000000.0_f64);
-    let mut kdbtree = if kdb {
-        let now = Instant::now(); 
-        let tree = KDBTree::from(&data);
-        println!("Finish buildnig KD-Buffer-Tree, takes {} s", now.elapsed().as_micros() as f64 / 1000000.0_f64);
-        Some(tree)
-    } else { None };
-    let zvtree = if zvs {
-        let now = Instant::now(); 
-        let tree = ZVTree::from(&data);
-        println!("Finish buildnig ZV-Tree, takes {} s", now.elapsed().as_micros() as f64 / 1000000.0_f64);
-        Some(tree)
-    } else { None };
-    let rstree = if rts | rto {
-        let now = Instant::now(); 
-        let tree = RSTree::from(&data);
-        println!("Finish buildnig RS-Tree, takes {} s", now.elapsed().as_micros() as f64 / 1000000.0_f64);
-        Some(tree)
-    } else { None };
-    let mut rsbtree = if rtb {
-        let now = Instant::now(); 
-        let tree = RSBTree::from(&data);
-        println!("Finish buildnig RSB-Tree, takes {} s", now.elapsed().as_micros() as f64 / 1000000.0_f64);
-        Some(tree)
-    } else { None };
+    let data: Vec<Point> = PointLoader::new().load(&config.input_file)?.report(&config.input_file);
 
-    let mut tot_range_size: usize = 0;
-    for range in config.ranges.iter() {
-        tot_range_size += kdtree.range(&range).len();
+    let mut workloads: Vec<Vec<MBR>> = Vec::new();
+    if !config.ranges.is_empty() {
+        workloads.push(config.ranges.clone());
     }
-    let avg_range_size = tot_range_size as f64 / config.ranges.len() as f64;
-
-    if qts {
-        for k in config.k_values.iter() {
-            let mut tot_time: u128 = 0;
-            for range in config.ranges.iter() {
-                let now = Instant::now();
-                let range_res = kdtree.range(&range);
-                let samples = sample_from(&range_res, k.clone());
-                tot_time += now.elapsed().as_micros();
-                assert_eq!(samples.len(), k.clone());
-            }
-            let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-            println!("qts {} {} {}", avg_range_size, k, avg_latency);
+    for path in config.range_files.iter() {
+        let (ranges, bad_lines) = loader::load_ranges(path)?;
+        for bad_line in bad_lines.iter() {
+            eprintln!("{}: skipped {}", path, bad_line);
         }
+        workloads.push(ranges);
     }
-    
-    if kds {
-        for k in config.k_values.iter() {
-            let mut tot_time: u128 = 0;
-            for range in config.ranges.iter() {
-                let now = Instant::now();
-                let samples = kdtree.range_sampling(&range, k.clone());
-                tot_time += now.elapsed().as_micros();
-                assert_eq!(samples.len(), k.clone());
-            }
-            let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-            println!("kds {} {} {}", avg_range_size, k, avg_latency);
-        }
-    }
-    
-    if let Some(tree) = &mut kdbtree {
-        for k in config.k_values.iter() {
-            let mut tot_time: u128 = 0;
-            for range in config.ranges.iter() {
-                let now = Instant::now();
-                let samples = tree.range_sampling(&range, k.clone());
-                tot_time += now.elapsed().as_micros();
-                assert_eq!(samples.len(), k.clone());
-            }
-            let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-            println!("kdb {} {} {}", avg_range_size, k, avg_latency);
+
+    // Samplers return at once on empty ranges, which would skew the
+    // latencies, so these are dropped; the selectivity still averages over
+    // all of them.
+    let kdtree = KDTree::from(&data);
+    let workloads: Vec<(Vec<MBR>, f64)> = workloads.into_iter().map(|ranges| {
+        let counts: Vec<usize> = ranges.iter().map(|range| kdtree.range_count(range)).collect();
+        let avg_range_size = counts.iter().sum::<usize>() as f64 / ranges.len().max(1) as f64;
+        let non_empty: Vec<MBR> = ranges.into_iter().zip(counts.iter()).filter(|(_, count)| **count > 0).map(|(range, _)| range).collect();
+        if non_empty.len() < counts.len() {
+            println!("skip {} empty ranges of workload with avg range size {}", counts.len() - non_empty.len(), avg_range_size);
         }
-    }
-    
-    if let Some(ztree) = &zvtree {
-        for k in config.k_values.iter() {
-            let mut tot_time: u128 = 0;
-            for range in config.ranges.iter() {
-                let now = Instant::now();
-                let samples = ztree.range_sampling(&range, k.clone());
-                tot_time += now.elapsed().as_micros();
-                assert_eq!(samples.len(), k.clone());
+        (non_empty, avg_range_size)
+    }).collect();
+
+    let mut results: Vec<BenchResult> = Vec::new();
+    for method in config.methods.iter() {
+        let name = match METHODS.iter().find(|(code, _)| code == method) {
+            Some((_, name)) => name,
+            None => {
+                eprintln!("Unknown method {}", method);
+                continue;
             }
-            let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-            println!("zvs {} {} {}", avg_range_size, k, avg_latency);
-        }
-    }
+        };
+        let now = Instant::now();
+        let mut index = NamedIndex::from_name(name, &data).unwrap();
+        let build_time = now.elapsed().as_micros() as f64 / 1000000.0_f64;
+        println!("Finish building {} ({}), takes {} s, index size = {}", method, name, build_time, index.size());
+        let max_intervals = config.max_intervals.unwrap_or(DEFAULT_BIGMIN_MAX_INTERVALS);
 
-    if let Some(rtree) = &rstree {
-        if rts {
-            for k in config.k_values.iter() {
-                let mut tot_time: u128 = 0;
-                for range in config.ranges.iter() {
-                    let now = Instant::now();
-                    let samples = rtree.range_sampling(&range, k.clone());
-                    tot_time += now.elapsed().as_micros();
-                    assert_eq!(samples.len(), k.clone());
-                }
-                let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-                println!("rts {} {} {}", avg_range_size, k, avg_latency);
+        for (ranges, avg_range_size) in workloads.iter() {
+            let tot_intervals: Option<usize> = match (method.as_str(), &index) {
+                ("zvs", NamedIndex::ZV(tree)) => Some(ranges.iter().map(|range| tree.interval_count(range)).sum()),
+                ("zvb", NamedIndex::ZV(tree)) => Some(ranges.iter().map(|range| tree.bigmin_interval_count(range, max_intervals)).sum()),
+                ("hvs", NamedIndex::HV(tree)) => Some(ranges.iter().map(|range| tree.interval_count(range)).sum()),
+                _ => None,
+            };
+            if let Some(tot_intervals) = tot_intervals {
+                println!("{} intervals {} {}", method, avg_range_size, tot_intervals as f64 / ranges.len().max(1) as f64);
             }
-        }
-        if rto {
             for k in config.k_values.iter() {
-                let mut tot_time: u128 = 0;
-                for range in config.ranges.iter() {
-                    let now = Instant::now();
-                    let samples = rtree.olken_range_sampling(&range, k.clone());
-                    tot_time += now.elapsed().as_micros();
-                    assert_eq!(samples.len(), k.clone());
-                }
-                let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-                println!("rto {} {} {}", avg_range_size, k, avg_latency);
+                let k = *k;
+                let latencies = bench::measure(ranges.len(), config.warmup, repetitions, &mut |i| {
+                    let samples = match (method.as_str(), &mut index) {
+                        ("zvb", NamedIndex::ZV(tree)) => tree.bigmin_range_sampling(&ranges[i], k, max_intervals),
+                        (_, index) => index.range_sampling(&ranges[i], k),
+                    };
+                    assert_eq!(samples.len(), k);
+                });
+                let latency = LatencySummary::from(&latencies);
+                // Method, avg range size, k, then mean, p50, p90, p99 and max latency in us.
+                println!("{} {} {} {} {} {} {} {}", method, avg_range_size, k, latency.mean, latency.p50, latency.p90, latency.p99, latency.max);
+                results.push(BenchResult {
+                    method: method.clone(),
+                    k,
+                    selectivity: *avg_range_size,
+                    queries: ranges.len(),
+                    warmup: config.warmup,
+                    repetitions,
+                    build_time,
+                    index_size: index.size(),
+                    latency,
+                });
             }
         }
     }
 
-    if let Some(rbtree) = &mut rsbtree {
-        for k in config.k_values.iter() {
-            let mut tot_time: u128 = 0;
-            for range in config.ranges.iter() {
-                let now = Instant::now();
-                let samples = rbtree.range_sampling(&range, k.clone());
-                tot_time += now.elapsed().as_micros();
-                assert_eq!(samples.len(), k.clone());
-            }
-            let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-            println!("rtb {} {} {}", avg_range_size, k, avg_latency);
-        }
-    }
-
-    if kdo {
-        for k in config.k_values.iter() {
-            let mut tot_time: u128 = 0;
-            for range in config.ranges.iter() {
-                let now = Instant::now();
-                let samples = kdtree.olken_range_sampling(&range, k.clone());
-                tot_time += now.elapsed().as_micros();
-                assert_eq!(samples.len(), k.clone());
-            }
-            let avg_latency = tot_time as f64 / config.ranges.len() as f64;
-            println!("kdo {} {} {}", avg_range_size, k, avg_latency);
-        }
+    if let Some(path) = &config.output {
+        bench::save(path, &results)?;
+        println!("Wrote {} results to {}", results.len(), path);
     }
-    Ok(()) 
-}
\ No newline at end of file
+    Ok(())
+}
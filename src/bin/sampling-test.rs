@@ -1,8 +1,5 @@
 extern crate range_sampling;
 
-use std::fs::File;
-use std::io::BufReader;
-use std::io::prelude::*;
 use std::env;
 use std::process;
 use std::time::Instant;
@@ -12,6 +9,7 @@ use range_sampling::index::kdtree::KDTree;
 use range_sampling::index::zvtree::ZVTree;
 use range_sampling::index::rstree::RSTree;
 use range_sampling::index::kdbtree::KDBTree;
+use range_sampling::loader::PointLoader;
 
 fn main() -> std::io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -24,25 +22,7 @@ fn main() -> std::io::Result<()> {
     // [low: (-117.520446, 47.58489), high: (-117.400446, 47.70489)]
     // [low: (-122.801398, 38.381212), high: (-122.681398, 38.501212)]
 
-    let mut data: Vec<Point> = Vec::new();
-    {
-        let input_file = File::open(&args[1])?;
-        let mut buf_reader = BufReader::new(&input_file);
-        let mut buf: String = String::new();
-        loop {
-            buf.clear();
-            let read_len = buf_reader.read_line(&mut buf)?;
-            if read_len == 0 {
-                break;
-            } else {
-                let nums: Vec<&str> = buf[0..buf.len() - 1].split(' ').collect();
-                assert_eq!(nums.len(), 3);
-                let x = nums[1].parse::<f64>().expect("Expect to be f64");
-                let y = nums[2].parse::<f64>().expect("Expect to be f64");
-                data.push(Point{x, y});
-            }
-        }
-    }
+    let data: Vec<Point> = PointLoader::new().columns(1, 2).load(&args[1])?.report(&args[1]);
 
     let query = MBR {
         low: Point {
@@ -67,9 +47,9 @@ fn main() -> std::io::Result<()> {
     //    },
     //};
 
-    let kdtree = KDTree::from(&mut data);
-    let mut kdbtree = KDBTree::from(&mut data);
-    let rstree = RSTree::from(&mut data);
+    let kdtree = KDTree::from(&data);
+    let mut kdbtree = KDBTree::from(&data);
+    let rstree = RSTree::from(&data);
     let zvtree = ZVTree::from(&data);
     {
         let now = Instant::now(); 
@@ -1,6 +1,9 @@
 use std::fmt;
 use serde::{Serialize, Deserialize};
 
+pub mod wkt;
+pub mod wkb;
+
 const RESOLUTION_X: f64 = 1e6;
 const RESOLUTION_Y: f64 = 1e6;
 const BASE_X: i32 = 180_000_000;
@@ -18,6 +21,65 @@ pub struct MBR {
     pub high: Point,
 }
 
+#[derive(PartialEq, Debug)]
+pub enum EncodeError {
+    OutOfDomain(Point),
+    InvalidDomain(MBR),
+    InvalidPrecision(f64),
+}
+
+// Maps coordinates of a fixed domain onto a 2^32 x 2^32 grid of cells with
+// side `precision`, from which z-values and hilbert values are derived.
+#[derive(PartialEq, Clone, Debug)]
+pub struct GridEncoder {
+    domain: MBR,
+    precision: f64,
+}
+
+// A query area that indexes can prune nodes against. contains_mbr may be
+// conservative, i.e. return false for a box the region covers.
+pub trait Region {
+    fn contains(&self, p: &Point) -> bool;
+    fn contains_mbr(&self, mbr: &MBR) -> bool;
+    fn intersects(&self, mbr: &MBR) -> bool;
+}
+
+// Union of possibly overlapping rectangles.
+#[derive(PartialEq, Clone, Debug)]
+pub struct MultiMBR {
+    pub mbrs: Vec<MBR>,
+}
+
+// Points of `include` outside of every rectangle in `excludes`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct MBRDifference {
+    pub include: MBR,
+    pub excludes: Vec<MBR>,
+}
+
+// Exterior ring followed by holes; a point is inside under the even-odd
+// rule over all rings.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Polygon {
+    rings: Vec<Vec<Point>>,
+    bounding_box: MBR,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Geometry {
+    Point(Point),
+    Envelope(MBR),
+    Polygon(Polygon),
+    MultiPolygon(Vec<Polygon>),
+}
+
+#[derive(PartialEq, Debug)]
+pub enum GeometryError {
+    Syntax(String),
+    Unsupported(String),
+    Truncated,
+}
+
 impl fmt::Display for Point {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "({}, {})", self.x, self.y)
@@ -30,6 +92,18 @@ impl fmt::Display for MBR {
     }
 }
 
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GeometryError::Syntax(msg) => write!(f, "invalid geometry: {}", msg),
+            GeometryError::Unsupported(what) => write!(f, "unsupported geometry {}", what),
+            GeometryError::Truncated => write!(f, "truncated geometry"),
+        }
+    }
+}
+
+impl std::error::Error for GeometryError {}
+
 impl Point {
     pub fn new(x_: f64, y_: f64) -> Point {
         Point { x: x_, y: y_ }
@@ -90,6 +164,63 @@ impl Point {
         res
     }
 
+    pub fn to_hvalue(&self) -> u64 {
+        let (x, y) = self.get_scaled();
+        Point::compose_hvalue(x, y)
+    }
+
+    pub fn from_hvalue(hv: u64) -> Point {
+        let (x, y) = Point::hvalue_to_raw(hv);
+        Point {
+            x: (x as i32 - BASE_X) as f64 / RESOLUTION_X,
+            y: (y as i32 - BASE_Y) as f64 / RESOLUTION_Y,
+        }
+    }
+
+    // Position of (x, y) along the Hilbert curve filling the 2^32 x 2^32 grid.
+    pub fn compose_hvalue(x: u32, y: u32) -> u64 {
+        let mut tmpx = x;
+        let mut tmpy = y;
+        let mut res: u64 = 0;
+        for i in (0..32).rev() {
+            let s: u32 = 1_u32 << i;
+            let rx: u64 = ((tmpx & s) != 0) as u64;
+            let ry: u64 = ((tmpy & s) != 0) as u64;
+            res += ((3 * rx) ^ ry) << (i * 2);
+            // Rotate the quadrant so that the sub-curve starts at its origin.
+            if ry == 0 {
+                if rx == 1 {
+                    tmpx = !tmpx;
+                    tmpy = !tmpy;
+                }
+                std::mem::swap(&mut tmpx, &mut tmpy);
+            }
+        }
+        res
+    }
+
+    pub fn hvalue_to_raw(hv: u64) -> (u32, u32) {
+        let mut tmpx: u32 = 0;
+        let mut tmpy: u32 = 0;
+        let mut tmp = hv;
+        for i in 0..32 {
+            let s: u32 = 1_u32 << i;
+            let rx: u32 = (1 & (tmp >> 1)) as u32;
+            let ry: u32 = (1 & (tmp ^ rx as u64)) as u32;
+            if ry == 0 {
+                if rx == 1 {
+                    tmpx = s - 1 - tmpx;
+                    tmpy = s - 1 - tmpy;
+                }
+                std::mem::swap(&mut tmpx, &mut tmpy);
+            }
+            tmpx += s * rx;
+            tmpy += s * ry;
+            tmp >>= 2;
+        }
+        (tmpx, tmpy)
+    }
+
     pub fn min_dist(&self, other: &Point) -> f64 {
         ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
     }
@@ -104,6 +235,127 @@ impl Point {
     }
 }
 
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::OutOfDomain(p) => write!(f, "point {} is outside of the encoder domain", p),
+            EncodeError::InvalidDomain(mbr) => write!(f, "invalid encoder domain {}", mbr),
+            EncodeError::InvalidPrecision(precision) => write!(f, "invalid precision {} for the encoder domain", precision),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl GridEncoder {
+    pub fn new(domain: &MBR, precision: f64) -> Result<GridEncoder, EncodeError> {
+        let valid = domain.low.x <= domain.high.x && domain.low.y <= domain.high.y;
+        if !valid || !domain.low.x.is_finite() || !domain.high.x.is_finite() ||
+           !domain.low.y.is_finite() || !domain.high.y.is_finite() {
+            return Err(EncodeError::InvalidDomain(domain.clone()));
+        }
+        let extent = (domain.high.x - domain.low.x).max(domain.high.y - domain.low.y);
+        if precision.is_nan() || precision <= 0.0 || extent / precision > u32::MAX as f64 {
+            return Err(EncodeError::InvalidPrecision(precision));
+        }
+        Ok(GridEncoder {
+            domain: domain.clone(),
+            precision,
+        })
+    }
+
+    pub fn from_points(points: &[Point], precision: f64) -> Result<GridEncoder, EncodeError> {
+        GridEncoder::new(&MBR::from_points(points), precision)
+    }
+
+    // Encoder over the bounding box of `points` with the finest precision that
+    // still fits the grid.
+    pub fn fit(points: &[Point]) -> GridEncoder {
+        let domain = MBR::from_points(points);
+        let extent = (domain.high.x - domain.low.x).max(domain.high.y - domain.low.y);
+        let precision = if extent > 0.0 && extent.is_finite() { extent / u32::MAX as f64 } else { 1.0 };
+        GridEncoder {
+            domain,
+            precision,
+        }
+    }
+
+    pub fn domain(&self) -> &MBR {
+        &self.domain
+    }
+
+    pub fn precision(&self) -> f64 {
+        self.precision
+    }
+
+    fn scale_x(&self, x: f64) -> u32 {
+        ((x - self.domain.low.x) / self.precision).floor().min(u32::MAX as f64) as u32
+    }
+
+    fn scale_y(&self, y: f64) -> u32 {
+        ((y - self.domain.low.y) / self.precision).floor().min(u32::MAX as f64) as u32
+    }
+
+    pub fn scale(&self, p: &Point) -> Result<(u32, u32), EncodeError> {
+        if !self.domain.contains(p) {
+            return Err(EncodeError::OutOfDomain(p.clone()));
+        }
+        Ok((self.scale_x(p.x), self.scale_y(p.y)))
+    }
+
+    // Grid cells (lowx, lowy, highx, highy) covered by a query, clamped to the
+    // domain. Returns None if the query misses the domain entirely.
+    pub fn scale_query(&self, query: &MBR) -> Option<(u32, u32, u32, u32)> {
+        if !query.intersects(&self.domain) || query.low.x > query.high.x || query.low.y > query.high.y {
+            return None;
+        }
+        Some((self.scale_x(query.low.x.max(self.domain.low.x)), self.scale_y(query.low.y.max(self.domain.low.y)),
+              self.scale_x(query.high.x.min(self.domain.high.x)), self.scale_y(query.high.y.min(self.domain.high.y))))
+    }
+
+    pub fn unscale(&self, x: u32, y: u32) -> Point {
+        Point {
+            x: self.domain.low.x + x as f64 * self.precision,
+            y: self.domain.low.y + y as f64 * self.precision,
+        }
+    }
+
+    // Area of the `side` x `side` block of cells at (x, y), padded by one cell
+    // so that it contains the points of the block despite rounding.
+    pub fn cell_mbr(&self, x: u32, y: u32, side: u64) -> MBR {
+        MBR {
+            low: Point {
+                x: self.domain.low.x + (x as f64 - 1.0) * self.precision,
+                y: self.domain.low.y + (y as f64 - 1.0) * self.precision,
+            },
+            high: Point {
+                x: self.domain.low.x + (x as f64 + side as f64 + 1.0) * self.precision,
+                y: self.domain.low.y + (y as f64 + side as f64 + 1.0) * self.precision,
+            },
+        }
+    }
+
+    pub fn to_zvalue(&self, p: &Point) -> Result<u64, EncodeError> {
+        let (x, y) = self.scale(p)?;
+        Ok(Point::compose_zvalue(x, y))
+    }
+
+    pub fn from_zvalue(&self, zv: u64) -> Point {
+        let (x, y) = Point::zvalue_to_raw(zv);
+        self.unscale(x, y)
+    }
+
+    pub fn to_hvalue(&self, p: &Point) -> Result<u64, EncodeError> {
+        let (x, y) = self.scale(p)?;
+        Ok(Point::compose_hvalue(x, y))
+    }
+
+    pub fn from_hvalue(&self, hv: u64) -> Point {
+        let (x, y) = Point::hvalue_to_raw(hv);
+        self.unscale(x, y)
+    }
+}
+
 impl MBR {
     pub fn new(low_: &Point, high_: &Point) -> MBR {
         MBR {
@@ -156,3 +408,219 @@ impl MBR {
           self.low.y > other.high.y || self.high.y < other.low.y)
     }
 }
+
+impl Region for MBR {
+    fn contains(&self, p: &Point) -> bool {
+        MBR::contains(self, p)
+    }
+
+    fn contains_mbr(&self, mbr: &MBR) -> bool {
+        MBR::contains_mbr(self, mbr)
+    }
+
+    fn intersects(&self, mbr: &MBR) -> bool {
+        MBR::intersects(self, mbr)
+    }
+}
+
+impl MultiMBR {
+    pub fn new(mbrs: &[MBR]) -> MultiMBR {
+        MultiMBR {
+            mbrs: mbrs.to_vec(),
+        }
+    }
+}
+
+impl Region for MultiMBR {
+    fn contains(&self, p: &Point) -> bool {
+        self.mbrs.iter().any(|mbr| mbr.contains(p))
+    }
+
+    fn contains_mbr(&self, mbr: &MBR) -> bool {
+        self.mbrs.iter().any(|other| other.contains_mbr(mbr))
+    }
+
+    fn intersects(&self, mbr: &MBR) -> bool {
+        self.mbrs.iter().any(|other| other.intersects(mbr))
+    }
+}
+
+impl MBRDifference {
+    pub fn new(include: &MBR, excludes: &[MBR]) -> MBRDifference {
+        MBRDifference {
+            include: include.clone(),
+            excludes: excludes.to_vec(),
+        }
+    }
+}
+
+impl Region for MBRDifference {
+    fn contains(&self, p: &Point) -> bool {
+        self.include.contains(p) && !self.excludes.iter().any(|mbr| mbr.contains(p))
+    }
+
+    fn contains_mbr(&self, mbr: &MBR) -> bool {
+        self.include.contains_mbr(mbr) && !self.excludes.iter().any(|other| other.intersects(mbr))
+    }
+
+    // Boxes inside a single exclusion are pruned.
+    fn intersects(&self, mbr: &MBR) -> bool {
+        self.include.intersects(mbr) && !self.excludes.iter().any(|other| other.contains_mbr(mbr))
+    }
+}
+
+// Whether segment a-b meets the closed box, by Liang-Barsky clipping.
+fn segment_intersects(a: &Point, b: &Point, mbr: &MBR) -> bool {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+    for (p, q) in [(-dx, a.x - mbr.low.x), (dx, mbr.high.x - a.x), (-dy, a.y - mbr.low.y), (dy, mbr.high.y - a.y)].iter() {
+        if *p == 0.0 {
+            if *q < 0.0 { return false; }
+        } else {
+            let t = q / p;
+            if *p < 0.0 { t0 = t0.max(t); } else { t1 = t1.min(t); }
+            if t0 > t1 { return false; }
+        }
+    }
+    true
+}
+
+impl Polygon {
+    pub fn new(rings: Vec<Vec<Point>>) -> Polygon {
+        let bounding_box = MBR::from_points(rings.first().map_or(&[][..], |ring| &ring[..]));
+        Polygon {
+            rings,
+            bounding_box,
+        }
+    }
+
+    pub fn rings(&self) -> &[Vec<Point>] {
+        &self.rings
+    }
+
+    pub fn bounding_box(&self) -> &MBR {
+        &self.bounding_box
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (&Point, &Point)> {
+        self.rings.iter().flat_map(|ring| ring.iter().zip(ring.iter().cycle().skip(1)))
+    }
+}
+
+impl Region for Polygon {
+    fn contains(&self, p: &Point) -> bool {
+        if !self.bounding_box.contains(p) {
+            return false;
+        }
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    fn contains_mbr(&self, mbr: &MBR) -> bool {
+        self.contains(&mbr.low) && !self.edges().any(|(a, b)| segment_intersects(a, b, mbr))
+    }
+
+    fn intersects(&self, mbr: &MBR) -> bool {
+        self.bounding_box.intersects(mbr) &&
+            (self.contains(&mbr.low) || self.edges().any(|(a, b)| segment_intersects(a, b, mbr)))
+    }
+}
+
+impl Geometry {
+    pub fn bounding_box(&self) -> MBR {
+        match self {
+            Geometry::Point(p) => MBR::new(p, p),
+            Geometry::Envelope(mbr) => mbr.clone(),
+            Geometry::Polygon(polygon) => polygon.bounding_box.clone(),
+            Geometry::MultiPolygon(polygons) => {
+                let corners: Vec<Point> = polygons.iter().flat_map(|polygon| vec![polygon.bounding_box.low.clone(), polygon.bounding_box.high.clone()]).collect();
+                MBR::from_points(&corners)
+            }
+        }
+    }
+}
+
+impl Region for Geometry {
+    fn contains(&self, p: &Point) -> bool {
+        match self {
+            Geometry::Point(point) => point == p,
+            Geometry::Envelope(mbr) => mbr.contains(p),
+            Geometry::Polygon(polygon) => polygon.contains(p),
+            Geometry::MultiPolygon(polygons) => polygons.iter().any(|polygon| polygon.contains(p)),
+        }
+    }
+
+    fn contains_mbr(&self, mbr: &MBR) -> bool {
+        match self {
+            Geometry::Point(point) => mbr.low == *point && mbr.high == *point,
+            Geometry::Envelope(other) => other.contains_mbr(mbr),
+            Geometry::Polygon(polygon) => polygon.contains_mbr(mbr),
+            Geometry::MultiPolygon(polygons) => polygons.iter().any(|polygon| polygon.contains_mbr(mbr)),
+        }
+    }
+
+    fn intersects(&self, mbr: &MBR) -> bool {
+        match self {
+            Geometry::Point(point) => mbr.contains(point),
+            Geometry::Envelope(other) => other.intersects(mbr),
+            Geometry::Polygon(polygon) => Region::intersects(polygon, mbr),
+            Geometry::MultiPolygon(polygons) => polygons.iter().any(|polygon| Region::intersects(polygon, mbr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn hvalue_round_trip() {
+        let mut rng = crate::util::seeded_rng(26);
+        for _ in 0..10000 {
+            let (x, y): (u32, u32) = (rng.gen(), rng.gen());
+            assert_eq!(Point::hvalue_to_raw(Point::compose_hvalue(x, y)), (x, y));
+        }
+        for (x, y) in [(0, 0), (u32::MAX, 0), (0, u32::MAX), (u32::MAX, u32::MAX)].iter() {
+            assert_eq!(Point::hvalue_to_raw(Point::compose_hvalue(*x, *y)), (*x, *y));
+        }
+    }
+
+    #[test]
+    fn hvalue_steps_to_neighbours() {
+        // The first 4^6 values fill the 64 x 64 cells at the origin, one
+        // cell after another.
+        let mut cells: Vec<(u32, u32)> = (0..4096).map(Point::hvalue_to_raw).collect();
+        for pair in cells.windows(2) {
+            let (x1, y1) = pair[0];
+            let (x2, y2) = pair[1];
+            assert_eq!((x1 as i64 - x2 as i64).abs() + (y1 as i64 - y2 as i64).abs(), 1);
+        }
+        assert!(cells.iter().all(|(x, y)| *x < 64 && *y < 64));
+        cells.sort_unstable();
+        cells.dedup();
+        assert_eq!(cells.len(), 4096);
+        assert_eq!(Point::compose_hvalue(63, 0), 4095);
+    }
+
+    #[test]
+    fn difference_prunes_only_excluded_boxes() {
+        let difference = MBRDifference::new(&MBR::new(&Point::new(0.0, 0.0), &Point::new(10.0, 10.0)),
+                                            &[MBR::new(&Point::new(2.0, 2.0), &Point::new(4.0, 4.0)),
+                                              MBR::new(&Point::new(3.0, 3.0), &Point::new(6.0, 6.0))]);
+        assert!(difference.contains(&Point::new(1.0, 1.0)));
+        assert!(!difference.contains(&Point::new(2.0, 2.0)));
+        assert!(!difference.contains(&Point::new(5.0, 5.0)));
+        assert!(!difference.contains(&Point::new(11.0, 5.0)));
+        // Inside one exclusion, or covered by both only together.
+        assert!(!difference.intersects(&MBR::new(&Point::new(2.5, 2.5), &Point::new(3.5, 3.5))));
+        assert!(difference.intersects(&MBR::new(&Point::new(2.5, 2.5), &Point::new(5.5, 5.5))));
+        assert!(difference.contains_mbr(&MBR::new(&Point::new(7.0, 0.0), &Point::new(10.0, 10.0))));
+        assert!(!difference.contains_mbr(&MBR::new(&Point::new(5.0, 0.0), &Point::new(10.0, 10.0))));
+    }
+}
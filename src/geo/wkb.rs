@@ -0,0 +1,214 @@
+use crate::geo::{Geometry, GeometryError, MBR, Point, Polygon};
+
+const WKB_POINT: u32 = 1;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTIPOLYGON: u32 = 6;
+// EWKB flags in the type of PostGIS geometries.
+const EWKB_Z: u32 = 0x8000_0000;
+const EWKB_M: u32 = 0x4000_0000;
+const EWKB_SRID: u32 = 0x2000_0000;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], GeometryError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(GeometryError::Truncated);
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn u32(&mut self) -> Result<u32, GeometryError> {
+        let mut buf = [0_u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(if self.little_endian { u32::from_le_bytes(buf) } else { u32::from_be_bytes(buf) })
+    }
+
+    fn f64(&mut self) -> Result<f64, GeometryError> {
+        let mut buf = [0_u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_bits(if self.little_endian { u64::from_le_bytes(buf) } else { u64::from_be_bytes(buf) }))
+    }
+
+    fn point(&mut self) -> Result<Point, GeometryError> {
+        Ok(Point { x: self.f64()?, y: self.f64()? })
+    }
+
+    // Counts are checked against the remaining bytes before allocating.
+    fn count(&mut self, item_size: usize) -> Result<usize, GeometryError> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(item_size) > self.bytes.len() - self.pos {
+            return Err(GeometryError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn polygon(&mut self) -> Result<Polygon, GeometryError> {
+        let mut rings: Vec<Vec<Point>> = Vec::new();
+        for _ in 0..self.count(4)? {
+            let mut ring: Vec<Point> = Vec::new();
+            for _ in 0..self.count(16)? {
+                ring.push(self.point()?);
+            }
+            if ring.len() < 3 {
+                return Err(GeometryError::Syntax("polygon ring with less than 3 points".to_string()));
+            }
+            rings.push(ring);
+        }
+        Ok(Polygon::new(rings))
+    }
+
+    // Byte order and type of a geometry; the byte order applies to the rest
+    // of that geometry.
+    fn header(&mut self) -> Result<u32, GeometryError> {
+        self.little_endian = match self.take(1)?[0] {
+            0 => false,
+            1 => true,
+            order => return Err(GeometryError::Syntax(format!("invalid byte order {}", order))),
+        };
+        let geometry_type = self.u32()?;
+        if geometry_type & (EWKB_Z | EWKB_M) != 0 || geometry_type & !EWKB_SRID > 1000 {
+            return Err(GeometryError::Unsupported("with more than two dimensions".to_string()));
+        }
+        if geometry_type & EWKB_SRID != 0 {
+            self.u32()?;
+        }
+        Ok(geometry_type & !EWKB_SRID)
+    }
+
+    fn geometry(&mut self) -> Result<Geometry, GeometryError> {
+        match self.header()? {
+            WKB_POINT => {
+                let p = self.point()?;
+                if p.x.is_nan() && p.y.is_nan() {
+                    return Err(GeometryError::Unsupported("POINT EMPTY".to_string()));
+                }
+                Ok(Geometry::Point(p))
+            }
+            WKB_POLYGON => Ok(Geometry::Polygon(self.polygon()?)),
+            WKB_MULTIPOLYGON => {
+                let mut polygons: Vec<Polygon> = Vec::new();
+                for _ in 0..self.count(9)? {
+                    if self.header()? != WKB_POLYGON {
+                        return Err(GeometryError::Syntax("expected polygons in MULTIPOLYGON".to_string()));
+                    }
+                    polygons.push(self.polygon()?);
+                }
+                Ok(Geometry::MultiPolygon(polygons))
+            }
+            other => Err(GeometryError::Unsupported(format!("WKB type {}", other))),
+        }
+    }
+}
+
+fn write_polygon(res: &mut Vec<u8>, polygon: &Polygon) {
+    res.push(1);
+    res.extend_from_slice(&WKB_POLYGON.to_le_bytes());
+    res.extend_from_slice(&(polygon.rings().len() as u32).to_le_bytes());
+    for ring in polygon.rings().iter() {
+        res.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+        for p in ring.iter() {
+            res.extend_from_slice(&p.x.to_le_bytes());
+            res.extend_from_slice(&p.y.to_le_bytes());
+        }
+    }
+}
+
+// WKB has no envelope type, so envelopes are written as closed polygons.
+fn envelope_polygon(mbr: &MBR) -> Polygon {
+    Polygon::new(vec![vec![
+        mbr.low.clone(),
+        Point::new(mbr.high.x, mbr.low.y),
+        mbr.high.clone(),
+        Point::new(mbr.low.x, mbr.high.y),
+        mbr.low.clone(),
+    ]])
+}
+
+impl Geometry {
+    pub fn from_wkb(bytes: &[u8]) -> Result<Geometry, GeometryError> {
+        let mut reader = Reader { bytes, pos: 0, little_endian: true };
+        let geometry = reader.geometry()?;
+        if reader.pos != bytes.len() {
+            return Err(GeometryError::Syntax("trailing bytes".to_string()));
+        }
+        Ok(geometry)
+    }
+
+    // Hex encoded WKB, as printed by PostGIS.
+    pub fn from_wkb_hex(text: &str) -> Result<Geometry, GeometryError> {
+        let text = text.trim();
+        if text.len() % 2 != 0 || !text.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(GeometryError::Syntax("invalid hex string".to_string()));
+        }
+        let bytes: Vec<u8> = (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect();
+        Geometry::from_wkb(&bytes)
+    }
+
+    // Little-endian WKB.
+    pub fn to_wkb(&self) -> Vec<u8> {
+        let mut res: Vec<u8> = Vec::new();
+        match self {
+            Geometry::Point(p) => {
+                res.push(1);
+                res.extend_from_slice(&WKB_POINT.to_le_bytes());
+                res.extend_from_slice(&p.x.to_le_bytes());
+                res.extend_from_slice(&p.y.to_le_bytes());
+            }
+            Geometry::Envelope(mbr) => write_polygon(&mut res, &envelope_polygon(mbr)),
+            Geometry::Polygon(polygon) => write_polygon(&mut res, polygon),
+            Geometry::MultiPolygon(polygons) => {
+                res.push(1);
+                res.extend_from_slice(&WKB_MULTIPOLYGON.to_le_bytes());
+                res.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+                for polygon in polygons.iter() {
+                    write_polygon(&mut res, polygon);
+                }
+            }
+        }
+        res
+    }
+
+    pub fn to_wkb_hex(&self) -> String {
+        self.to_wkb().iter().map(|b| format!("{:02X}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(low: f64, high: f64) -> Vec<Point> {
+        vec![Point::new(low, low), Point::new(high, low), Point::new(high, high), Point::new(low, high), Point::new(low, low)]
+    }
+
+    #[test]
+    fn round_trip() {
+        let geometries = [
+            Geometry::Point(Point::new(-1.5, 1e-7)),
+            Geometry::Polygon(Polygon::new(vec![square(0.0, 10.0), square(2.0, 3.0)])),
+            Geometry::MultiPolygon(vec![Polygon::new(vec![square(0.0, 1.0)]), Polygon::new(vec![square(5.0, 6.5)])]),
+        ];
+        for geometry in geometries.iter() {
+            assert_eq!(&Geometry::from_wkb(&geometry.to_wkb()).unwrap(), geometry);
+            assert_eq!(&Geometry::from_wkb_hex(&geometry.to_wkb_hex()).unwrap(), geometry);
+        }
+        // WKB has no envelopes, they are written as their polygon.
+        let envelope = Geometry::Envelope(MBR::new(&Point::new(0.0, 0.0), &Point::new(1.0, 1.0)));
+        assert_eq!(Geometry::from_wkb(&envelope.to_wkb()).unwrap(), Geometry::Polygon(Polygon::new(vec![square(0.0, 1.0)])));
+    }
+
+    #[test]
+    fn reads_postgis_hex() {
+        // Big-endian POINT (1 2), and the same point as EWKB with SRID 4326.
+        assert_eq!(Geometry::from_wkb_hex("00000000013FF00000000000004000000000000000").unwrap(), Geometry::Point(Point::new(1.0, 2.0)));
+        assert_eq!(Geometry::from_wkb_hex("0101000020E6100000000000000000F03F0000000000000040").unwrap(), Geometry::Point(Point::new(1.0, 2.0)));
+        assert!(Geometry::from_wkb_hex("0101000000000000000000F03F00000000000000400").is_err());
+        assert_eq!(Geometry::from_wkb_hex("0101000000000000000000F03F"), Err(GeometryError::Truncated));
+    }
+}
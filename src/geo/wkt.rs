@@ -0,0 +1,180 @@
+use crate::geo::{Geometry, GeometryError, MBR, Point, Polygon};
+
+// Parser over the text of one WKT geometry, with an optional EWKT
+// "SRID=...;" prefix. ENVELOPE follows the CQL order (minx, maxx, maxy, miny).
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+fn syntax(msg: &str) -> GeometryError {
+    GeometryError::Syntax(msg.to_string())
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), GeometryError> {
+        match self.peek() {
+            Some(next) if next == c => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(next) => Err(GeometryError::Syntax(format!("expected '{}' at {}, found '{}'", c, self.pos, next))),
+            None => Err(GeometryError::Truncated),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        self.pos += len;
+        rest[..len].to_ascii_uppercase()
+    }
+
+    fn number(&mut self) -> Result<f64, GeometryError> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let len = rest.find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c))).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(if rest.is_empty() { GeometryError::Truncated } else { GeometryError::Syntax(format!("expected a number at {}", self.pos)) });
+        }
+        let value = rest[..len].parse::<f64>().map_err(|_| GeometryError::Syntax(format!("invalid number {:?}", &rest[..len])))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    fn point(&mut self) -> Result<Point, GeometryError> {
+        let x = self.number()?;
+        let y = self.number()?;
+        if self.peek().map_or(false, |c| c != ',' && c != ')') {
+            return Err(GeometryError::Unsupported("with more than two dimensions".to_string()));
+        }
+        Ok(Point { x, y })
+    }
+
+    // Comma separated items in parentheses.
+    fn list<T>(&mut self, item: &dyn Fn(&mut Parser<'a>) -> Result<T, GeometryError>) -> Result<Vec<T>, GeometryError> {
+        self.expect('(')?;
+        let mut items: Vec<T> = vec![item(self)?];
+        while self.peek() == Some(',') {
+            self.expect(',')?;
+            items.push(item(self)?);
+        }
+        self.expect(')')?;
+        Ok(items)
+    }
+
+    fn polygon(&mut self) -> Result<Polygon, GeometryError> {
+        let rings = self.list(&|parser| parser.list(&|parser| parser.point()))?;
+        if rings.iter().any(|ring| ring.len() < 3) {
+            return Err(syntax("polygon ring with less than 3 points"));
+        }
+        Ok(Polygon::new(rings))
+    }
+
+    fn geometry(&mut self) -> Result<Geometry, GeometryError> {
+        let keyword = self.word();
+        if keyword == "SRID" {
+            self.expect('=')?;
+            self.number()?;
+            self.expect(';')?;
+            return self.geometry();
+        }
+        if self.peek().map_or(false, |c| c.is_ascii_alphabetic()) {
+            let modifier = self.word();
+            return Err(GeometryError::Unsupported(format!("{} {}", keyword, modifier)));
+        }
+        let geometry = match keyword.as_str() {
+            "POINT" => {
+                self.expect('(')?;
+                let p = self.point()?;
+                self.expect(')')?;
+                Geometry::Point(p)
+            }
+            "POLYGON" => Geometry::Polygon(self.polygon()?),
+            "MULTIPOLYGON" => Geometry::MultiPolygon(self.list(&|parser| parser.polygon())?),
+            "ENVELOPE" => {
+                let v = self.list(&|parser| parser.number())?;
+                if v.len() != 4 {
+                    return Err(syntax("expected 4 values in ENVELOPE"));
+                }
+                Geometry::Envelope(MBR::new(&Point::new(v[0], v[3]), &Point::new(v[1], v[2])))
+            }
+            "" => return Err(if self.peek().is_none() { GeometryError::Truncated } else { syntax("expected a geometry type") }),
+            other => return Err(GeometryError::Unsupported(other.to_string())),
+        };
+        if self.peek().is_some() {
+            return Err(GeometryError::Syntax(format!("trailing text at {}", self.pos)));
+        }
+        Ok(geometry)
+    }
+}
+
+fn ring_to_wkt(ring: &[Point]) -> String {
+    let points: Vec<String> = ring.iter().map(|p| format!("{} {}", p.x, p.y)).collect();
+    format!("({})", points.join(", "))
+}
+
+fn polygon_to_wkt(polygon: &Polygon) -> String {
+    let rings: Vec<String> = polygon.rings().iter().map(|ring| ring_to_wkt(ring)).collect();
+    format!("({})", rings.join(", "))
+}
+
+impl Geometry {
+    pub fn from_wkt(text: &str) -> Result<Geometry, GeometryError> {
+        Parser { text, pos: 0 }.geometry()
+    }
+
+    pub fn to_wkt(&self) -> String {
+        match self {
+            Geometry::Point(p) => format!("POINT ({} {})", p.x, p.y),
+            Geometry::Envelope(mbr) => format!("ENVELOPE ({}, {}, {}, {})", mbr.low.x, mbr.high.x, mbr.high.y, mbr.low.y),
+            Geometry::Polygon(polygon) => format!("POLYGON {}", polygon_to_wkt(polygon)),
+            Geometry::MultiPolygon(polygons) => {
+                let polygons: Vec<String> = polygons.iter().map(polygon_to_wkt).collect();
+                format!("MULTIPOLYGON ({})", polygons.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(low: f64, high: f64) -> Vec<Point> {
+        vec![Point::new(low, low), Point::new(high, low), Point::new(high, high), Point::new(low, high), Point::new(low, low)]
+    }
+
+    #[test]
+    fn round_trip() {
+        let geometries = [
+            Geometry::Point(Point::new(-1.5, 1e-7)),
+            Geometry::Envelope(MBR::new(&Point::new(0.0, -2.0), &Point::new(3.25, 4.0))),
+            Geometry::Polygon(Polygon::new(vec![square(0.0, 10.0), square(2.0, 3.0)])),
+            Geometry::MultiPolygon(vec![Polygon::new(vec![square(0.0, 1.0)]), Polygon::new(vec![square(5.0, 6.5)])]),
+        ];
+        for geometry in geometries.iter() {
+            assert_eq!(&Geometry::from_wkt(&geometry.to_wkt()).unwrap(), geometry);
+        }
+    }
+
+    #[test]
+    fn reads_postgis_text() {
+        assert_eq!(Geometry::from_wkt("SRID=4326;point(1 2)").unwrap(), Geometry::Point(Point::new(1.0, 2.0)));
+        assert_eq!(Geometry::from_wkt("POINT Z (1 2 3)"), Err(GeometryError::Unsupported("POINT Z".to_string())));
+        assert_eq!(Geometry::from_wkt("POINT (1 2"), Err(GeometryError::Truncated));
+        assert!(Geometry::from_wkt("POINT (1 2) x").is_err());
+        assert!(Geometry::from_wkt("LINESTRING (0 0, 1 1)").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use crate::geo::Point;
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+// Point features of a GeoJSON FeatureCollection; properties[i] is the payload
+// of points[i].
+pub struct FeatureCollection {
+    pub points: Vec<Point>,
+    pub properties: Vec<Value>,
+    pub skipped: Vec<SkippedFeature>,
+}
+
+// A feature that is not a valid Point, indexed from 0.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SkippedFeature {
+    pub index: usize,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub enum GeoJsonError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    NotFeatureCollection,
+}
+
+impl fmt::Display for SkippedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "feature {}: {}", self.index, self.reason)
+    }
+}
+
+impl fmt::Display for GeoJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GeoJsonError::Io(err) => write!(f, "{}", err),
+            GeoJsonError::Json(err) => write!(f, "invalid json: {}", err),
+            GeoJsonError::NotFeatureCollection => write!(f, "expected a FeatureCollection"),
+        }
+    }
+}
+
+impl std::error::Error for GeoJsonError {}
+
+impl From<std::io::Error> for GeoJsonError {
+    fn from(err: std::io::Error) -> GeoJsonError {
+        GeoJsonError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for GeoJsonError {
+    fn from(err: serde_json::Error) -> GeoJsonError {
+        GeoJsonError::Json(err)
+    }
+}
+
+fn parse_point(feature: &Value) -> Result<Point, String> {
+    if feature.get("type").and_then(Value::as_str) != Some("Feature") {
+        return Err("not a Feature".to_string());
+    }
+    let geometry = feature.get("geometry").filter(|g| !g.is_null()).ok_or("missing geometry")?;
+    match geometry.get("type").and_then(Value::as_str) {
+        Some("Point") => {}
+        Some(other) => return Err(format!("unsupported geometry {}", other)),
+        None => return Err("geometry without type".to_string()),
+    }
+    let coordinates = geometry.get("coordinates").and_then(Value::as_array).ok_or("missing coordinates")?;
+    match (coordinates.first().and_then(Value::as_f64), coordinates.get(1).and_then(Value::as_f64)) {
+        (Some(x), Some(y)) => Ok(Point { x, y }),
+        _ => Err("expected numeric [x, y] coordinates".to_string()),
+    }
+}
+
+impl FeatureCollection {
+    pub fn load(path: &str) -> Result<FeatureCollection, GeoJsonError> {
+        FeatureCollection::read(BufReader::new(File::open(path)?))
+    }
+
+    // Features other than Points are skipped and reported.
+    pub fn read<R: Read>(reader: R) -> Result<FeatureCollection, GeoJsonError> {
+        let mut root: Value = serde_json::from_reader(reader)?;
+        if root.get("type").and_then(Value::as_str) != Some("FeatureCollection") {
+            return Err(GeoJsonError::NotFeatureCollection);
+        }
+        let features = match root.get_mut("features").map(Value::take) {
+            Some(Value::Array(features)) => features,
+            _ => return Err(GeoJsonError::NotFeatureCollection),
+        };
+        let mut collection = FeatureCollection {
+            points: Vec::new(),
+            properties: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for (index, mut feature) in features.into_iter().enumerate() {
+            match parse_point(&feature) {
+                Ok(p) => {
+                    collection.points.push(p);
+                    collection.properties.push(feature.get_mut("properties").map_or(Value::Null, Value::take));
+                }
+                Err(reason) => collection.skipped.push(SkippedFeature { index, reason }),
+            }
+        }
+        Ok(collection)
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        write_features(BufWriter::new(File::create(path)?), &self.points, &self.properties)
+    }
+}
+
+pub fn write_points<W: Write>(writer: W, points: &[Point]) -> std::io::Result<()> {
+    write_features(writer, points, &[])
+}
+
+// Writes one feature per point, streaming so that large samples are not
+// held as a json tree. Points beyond `properties` get null properties.
+pub fn write_features<W: Write>(mut writer: W, points: &[Point], properties: &[Value]) -> std::io::Result<()> {
+    writer.write_all(b"{\"type\":\"FeatureCollection\",\"features\":[")?;
+    for (i, p) in points.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        let mut feature = Map::new();
+        feature.insert("type".to_string(), json!("Feature"));
+        feature.insert("geometry".to_string(), json!({"type": "Point", "coordinates": [p.x, p.y]}));
+        feature.insert("properties".to_string(), properties.get(i).cloned().unwrap_or(Value::Null));
+        serde_json::to_writer(&mut writer, &feature)?;
+    }
+    writer.write_all(b"]}\n")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_keeps_properties() {
+        let points = vec![Point::new(1.5, -2.0), Point::new(0.0, 1e10)];
+        let properties = vec![json!({"name": "a", "rank": 1}), json!(null)];
+        let mut buffer: Vec<u8> = Vec::new();
+        write_features(&mut buffer, &points, &properties).unwrap();
+        let collection = FeatureCollection::read(buffer.as_slice()).unwrap();
+        assert_eq!(collection.points, points);
+        assert_eq!(collection.properties, properties);
+        assert!(collection.skipped.is_empty());
+    }
+
+    #[test]
+    fn skips_features_other_than_points() {
+        let text = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "geometry": {"type": "Point", "coordinates": [1, 2, 3]}},
+            {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}},
+            {"type": "Feature", "geometry": null, "properties": {}},
+            {"type": "Feature", "geometry": {"type": "Point", "coordinates": ["1", 2]}}
+        ]}"#;
+        let collection = FeatureCollection::read(text.as_bytes()).unwrap();
+        assert_eq!(collection.points, vec![Point::new(1.0, 2.0)]);
+        assert_eq!(collection.properties, vec![Value::Null]);
+        let skipped: Vec<usize> = collection.skipped.iter().map(|feature| feature.index).collect();
+        assert_eq!(skipped, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        match FeatureCollection::read(r#"{"type": "Feature", "features": []}"#.as_bytes()) {
+            Err(GeoJsonError::NotFeatureCollection) => {}
+            _ => panic!("expected NotFeatureCollection"),
+        }
+        match FeatureCollection::read("{".as_bytes()) {
+            Err(GeoJsonError::Json(_)) => {}
+            _ => panic!("expected a json error"),
+        }
+    }
+}
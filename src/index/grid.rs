@@ -0,0 +1,250 @@
+use crate::geo::{MBR, Point};
+use crate::alias::AliasTable;
+use crate::util;
+use crate::index::{BuildError, check_param};
+use rand::distributions::{Uniform, Distribution};
+
+const GRID_TARGET_OCCUPANCY: usize = 64;
+
+// Cell columns and rows (x1, x2, y1, y2).
+type CellRange = (usize, usize, usize, usize);
+
+pub struct GridIndex {
+    bounding_box: MBR,
+    nx: usize,
+    ny: usize,
+    cell_width: f64,
+    cell_height: f64,
+    // Offset of every cell in data; cells are laid out column by column, so
+    // a run of cells within one column is contiguous.
+    cell_start: Vec<usize>,
+    // prefix[i * (ny + 1) + j] counts the points in columns < i and rows < j.
+    prefix: Vec<usize>,
+    data: Vec<Point>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GridIndexBuilder {
+    target_occupancy: usize,
+}
+
+impl GridIndexBuilder {
+    pub fn new() -> GridIndexBuilder {
+        GridIndexBuilder {
+            target_occupancy: GRID_TARGET_OCCUPANCY,
+        }
+    }
+
+    // Average number of points per cell the grid is sized for.
+    pub fn target_occupancy(mut self, target_occupancy: usize) -> GridIndexBuilder {
+        self.target_occupancy = target_occupancy;
+        self
+    }
+
+    pub fn build(&self, data: &[Point]) -> Result<GridIndex, BuildError> {
+        check_param("target_occupancy", self.target_occupancy, 1)?;
+        Ok(GridIndex::build(data, self.target_occupancy))
+    }
+}
+
+impl Default for GridIndexBuilder {
+    fn default() -> GridIndexBuilder {
+        GridIndexBuilder::new()
+    }
+}
+
+impl GridIndex {
+    pub fn from(data: &[Point]) -> GridIndex {
+        GridIndex::build(data, GRID_TARGET_OCCUPANCY)
+    }
+
+    pub fn builder() -> GridIndexBuilder {
+        GridIndexBuilder::new()
+    }
+
+    fn build(data: &[Point], target_occupancy: usize) -> GridIndex {
+        let bounding_box = MBR::from_points(data);
+        let width = bounding_box.high.x - bounding_box.low.x;
+        let height = bounding_box.high.y - bounding_box.low.y;
+        let cells = (data.len() / target_occupancy).max(1);
+        let (nx, ny) = if width > 0.0 && height > 0.0 {
+            let nx = ((cells as f64 * width / height).sqrt().ceil() as usize).clamp(1, cells);
+            (nx, (cells as f64 / nx as f64).ceil() as usize)
+        } else if width > 0.0 {
+            (cells, 1)
+        } else if height > 0.0 {
+            (1, cells)
+        } else {
+            (1, 1)
+        };
+        let cell_width = if width > 0.0 { width / nx as f64 } else { 1.0 };
+        let cell_height = if height > 0.0 { height / ny as f64 } else { 1.0 };
+        let mut grid = GridIndex {
+            bounding_box,
+            nx,
+            ny,
+            cell_width,
+            cell_height,
+            cell_start: vec![0; nx * ny + 1],
+            prefix: vec![0; (nx + 1) * (ny + 1)],
+            data: Vec::new(),
+        };
+
+        // Counting sort of the points by cell.
+        let cell_ids: Vec<usize> = data.iter().map(|p| grid.cell_x(p.x) * ny + grid.cell_y(p.y)).collect();
+        for id in cell_ids.iter() {
+            grid.cell_start[id + 1] += 1;
+        }
+        for i in 0..nx {
+            for j in 0..ny {
+                let cnt = grid.cell_start[i * ny + j + 1];
+                grid.prefix[(i + 1) * (ny + 1) + j + 1] = cnt + grid.prefix[i * (ny + 1) + j + 1]
+                    + grid.prefix[(i + 1) * (ny + 1) + j] - grid.prefix[i * (ny + 1) + j];
+            }
+        }
+        for i in 0..nx * ny {
+            grid.cell_start[i + 1] += grid.cell_start[i];
+        }
+        let mut next: Vec<usize> = grid.cell_start.clone();
+        let mut layout: Vec<Point> = vec![Point::new(0.0, 0.0); data.len()];
+        for (p, id) in data.iter().zip(cell_ids.iter()) {
+            layout[next[*id]] = p.clone();
+            next[*id] += 1;
+        }
+        grid.data = layout;
+        grid
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len() * 16 + (self.cell_start.len() + self.prefix.len()) * 8 + 80
+    }
+
+    fn cell_x(&self, x: f64) -> usize {
+        (((x - self.bounding_box.low.x) / self.cell_width) as usize).min(self.nx - 1)
+    }
+
+    fn cell_y(&self, y: f64) -> usize {
+        (((y - self.bounding_box.low.y) / self.cell_height) as usize).min(self.ny - 1)
+    }
+
+    fn count(&self, x1: usize, x2: usize, y1: usize, y2: usize) -> usize {
+        if x1 >= x2 || y1 >= y2 {
+            return 0;
+        }
+        let stride = self.ny + 1;
+        self.prefix[x2 * stride + y2] + self.prefix[x1 * stride + y1]
+            - self.prefix[x1 * stride + y2] - self.prefix[x2 * stride + y1]
+    }
+
+    // Cells (cx1, cx2, cy1, cy2) touched by the query, inclusive, plus the
+    // half-open cell ranges (ix1, ix2, iy1, iy2) whose points all lie within
+    // the query. A cell is interior when it lies strictly between the cells of
+    // the query borders, or when the query border lies outside of the data.
+    fn cover(&self, query: &MBR) -> (CellRange, CellRange) {
+        let (cx1, cx2) = (self.cell_x(query.low.x), self.cell_x(query.high.x));
+        let (cy1, cy2) = (self.cell_y(query.low.y), self.cell_y(query.high.y));
+        let ix1 = if query.low.x <= self.bounding_box.low.x { cx1 } else { cx1 + 1 };
+        let ix2 = if query.high.x >= self.bounding_box.high.x { cx2 + 1 } else { cx2 };
+        let iy1 = if query.low.y <= self.bounding_box.low.y { cy1 } else { cy1 + 1 };
+        let iy2 = if query.high.y >= self.bounding_box.high.y { cy2 + 1 } else { cy2 };
+        ((cx1, cx2, cy1, cy2), (ix1, ix2, iy1, iy2))
+    }
+
+    fn column_run(&self, i: usize, y1: usize, y2: usize) -> (usize, usize) {
+        (self.cell_start[i * self.ny + y1], self.cell_start[i * self.ny + y2])
+    }
+
+    // Non-empty runs of data (start, end, interior) in the cells touched by
+    // the query, where the points of interior runs all lie within it. Each
+    // column has at most an edge run below, an interior run and one above.
+    fn runs(&self, query: &MBR) -> Vec<(usize, usize, bool)> {
+        let ((cx1, cx2, cy1, cy2), (ix1, ix2, iy1, iy2)) = self.cover(query);
+        let mut runs: Vec<(usize, usize, bool)> = Vec::new();
+        let mut push = |(start, end): (usize, usize), interior: bool| {
+            if start < end {
+                runs.push((start, end, interior));
+            }
+        };
+        for i in cx1..=cx2 {
+            if i >= ix1 && i < ix2 {
+                if cy1 < iy1 { push(self.column_run(i, cy1, iy1), false); }
+                if iy1 < iy2 { push(self.column_run(i, iy1, iy2), true); }
+                if iy2 <= cy2 { push(self.column_run(i, iy2.max(iy1), cy2 + 1), false); }
+            } else {
+                push(self.column_run(i, cy1, cy2 + 1), false);
+            }
+        }
+        runs
+    }
+
+    pub fn range(&self, query: &MBR) -> Vec<Point> {
+        let mut res: Vec<Point> = Vec::new();
+        if self.data.is_empty() || !query.intersects(&self.bounding_box) {
+            return res;
+        }
+        for (start, end, interior) in self.runs(query).into_iter() {
+            if interior {
+                res.extend_from_slice(&self.data[start..end]);
+            } else {
+                res.extend(self.data[start..end].iter().filter(|p| query.contains(p)).cloned());
+            }
+        }
+        res
+    }
+
+    pub fn range_count(&self, query: &MBR) -> usize {
+        if self.data.is_empty() || !query.intersects(&self.bounding_box) {
+            return 0;
+        }
+        let ((cx1, cx2, cy1, cy2), (ix1, ix2, iy1, iy2)) = self.cover(query);
+        let mut res = self.count(ix1, ix2, iy1, iy2);
+        // Scan the edge cells around the interior block.
+        for i in cx1..=cx2 {
+            let edges = if i >= ix1 && i < ix2 {
+                let mut edges: Vec<(usize, usize)> = Vec::new();
+                if cy1 < iy1 { edges.push(self.column_run(i, cy1, iy1)); }
+                if iy2 <= cy2 { edges.push(self.column_run(i, iy2.max(iy1), cy2 + 1)); }
+                edges
+            } else {
+                vec![self.column_run(i, cy1, cy2 + 1)]
+            };
+            for (start, end) in edges.into_iter() {
+                res += self.data[start..end].iter().filter(|p| query.contains(p)).count();
+            }
+        }
+        res
+    }
+
+    pub fn range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        if self.data.is_empty() || !query.intersects(&self.bounding_box) {
+            return samples;
+        }
+        // Every run is an entry of the top level alias table; points drawn
+        // from interior runs are accepted without a check.
+        let candidates = self.runs(query);
+        let holds_any = candidates.iter().any(|(_, _, interior)| *interior) ||
+            util::holds_any(query, candidates.iter().map(|(start, end, _)| &self.data[*start..*end]));
+        if !holds_any {
+            return samples;
+        }
+        let weights: Vec<f64> = candidates.iter().map(|(start, end, _)| (end - start) as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+
+        // For each sample, construct two level sampling.
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let (start, _, interior) = candidates[res];
+            let offset = (weights[res] * coin3) as usize + start;
+            if interior || query.contains(&self.data[offset]) {
+                samples.push(self.data[offset].clone());
+            }
+        }
+        samples
+    }
+}
@@ -0,0 +1,248 @@
+use crate::alias::AliasTable;
+use crate::geo::{MBR, Point, GridEncoder, EncodeError};
+use crate::util;
+use crate::index::{BuildError, check_param};
+use superslice::*;
+use std::rc::Rc;
+use rand::distributions::{Uniform, Distribution};
+
+const MAX_ENTRIES_PER_NODE: usize = 256;
+
+struct HVTreeNode {
+    children: Option<Vec<Rc<HVTreeNode>>>,
+    start: usize,
+    end: usize,
+}
+
+impl HVTreeNode {
+    fn from(level: u8, high_bits: u64, data: &[u64], start: usize, end: usize, max_entries: usize) -> HVTreeNode {
+        if level == 32 || end - start <= max_entries {
+            HVTreeNode {
+                children: None,
+                start,
+                end,
+            }
+        } else {
+            // Children are the four quadrants in curve order, each one a
+            // contiguous run of hilbert values sharing the next two bits.
+            let shift: u8 = (31 - level) * 2;
+            let low_bits: u64 = (1_u64 << shift) - 1;
+            let data_slice = &data[start..end];
+            let mut children: Vec<Rc<HVTreeNode>> = Vec::new();
+            for i in 0..4_u64 {
+                let bound = high_bits + (i << shift);
+                children.push(Rc::new(HVTreeNode::from(level + 1, bound, data,
+                    start + data_slice.lower_bound(&bound), start + data_slice.upper_bound(&(bound + low_bits)), max_entries)));
+            }
+            HVTreeNode {
+                children: Some(children),
+                start,
+                end,
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        16 + if let Some(children) = &self.children {
+            let mut res = 24 + children.len() * 8;
+            for child in children.iter() {
+                res += child.size();
+            }
+            res
+        } else { 0 }
+    }
+}
+
+pub struct HVTree {
+    root: HVTreeNode,
+    data: Vec<u64>,
+    // Original coordinates, permuted alongside the sorted hilbert values.
+    points: Vec<Point>,
+    encoder: GridEncoder,
+}
+
+#[derive(Clone, Debug)]
+pub struct HVTreeBuilder {
+    max_entries_per_node: usize,
+    encoder: Option<GridEncoder>,
+}
+
+impl HVTreeBuilder {
+    pub fn new() -> HVTreeBuilder {
+        HVTreeBuilder {
+            max_entries_per_node: MAX_ENTRIES_PER_NODE,
+            encoder: None,
+        }
+    }
+
+    pub fn max_entries_per_node(mut self, max_entries_per_node: usize) -> HVTreeBuilder {
+        self.max_entries_per_node = max_entries_per_node;
+        self
+    }
+
+    // Defaults to an encoder fitted to the input.
+    pub fn encoder(mut self, encoder: GridEncoder) -> HVTreeBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    pub fn build(&self, input: &[Point]) -> Result<HVTree, BuildError> {
+        check_param("max_entries_per_node", self.max_entries_per_node, 1)?;
+        let encoder = self.encoder.clone().unwrap_or_else(|| GridEncoder::fit(input));
+        HVTree::build(input, encoder, self.max_entries_per_node).map_err(BuildError::from)
+    }
+}
+
+impl Default for HVTreeBuilder {
+    fn default() -> HVTreeBuilder {
+        HVTreeBuilder::new()
+    }
+}
+
+impl HVTree {
+    pub fn from(input: &[Point]) -> HVTree {
+        HVTree::with_encoder(input, GridEncoder::fit(input)).expect("Expect finite coordinates")
+    }
+
+    pub fn builder() -> HVTreeBuilder {
+        HVTreeBuilder::new()
+    }
+
+    pub fn with_encoder(input: &[Point], encoder: GridEncoder) -> Result<HVTree, EncodeError> {
+        HVTree::build(input, encoder, MAX_ENTRIES_PER_NODE)
+    }
+
+    fn build(input: &[Point], encoder: GridEncoder, max_entries: usize) -> Result<HVTree, EncodeError> {
+        let mut entries: Vec<(u64, Point)> = Vec::new();
+        for p in input.iter() {
+            entries.push((encoder.to_hvalue(p)?, p.clone()));
+        }
+        entries.sort_unstable_by_key(|e| e.0);
+        let (data, points): (Vec<u64>, Vec<Point>) = entries.into_iter().unzip();
+        Ok(HVTree {
+            root: HVTreeNode::from(0, 0, &data, 0, data.len(), max_entries),
+            data,
+            points,
+            encoder,
+        })
+    }
+
+    pub fn encoder(&self) -> &GridEncoder {
+        &self.encoder
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len() * 24 + self.root.size() + 40
+    }
+
+    fn check_bound(&self, offset: usize, query: &MBR) -> bool {
+        query.contains(&self.points[offset])
+    }
+
+    fn push_interval(intervals: &mut Vec<(usize, usize)>, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+        // Neighbouring cells along the curve are adjacent in data, so merge them.
+        if let Some(last) = intervals.last_mut() {
+            if last.1 == start {
+                last.1 = end;
+                return;
+            }
+        }
+        intervals.push((start, end));
+    }
+
+    fn range_intervals(&self, node: &HVTreeNode, high_bits: u64, level: u32, bound: (u32, u32, u32, u32), intervals: &mut Vec<(usize, usize)>) {
+        // The cell of a node is the aligned square holding the point decoded
+        // from its hilbert prefix.
+        let side: u64 = 1_u64 << (32 - level);
+        let (x, y) = Point::hvalue_to_raw(high_bits);
+        let cell_x: u64 = x as u64 & !(side - 1);
+        let cell_y: u64 = y as u64 & !(side - 1);
+        let (lowx, lowy, highx, highy) = (bound.0 as u64, bound.1 as u64, bound.2 as u64, bound.3 as u64);
+        if cell_x > highx || cell_x + side - 1 < lowx || cell_y > highy || cell_y + side - 1 < lowy {
+            return;
+        }
+        if lowx <= cell_x && cell_x + side - 1 <= highx && lowy <= cell_y && cell_y + side - 1 <= highy {
+            HVTree::push_interval(intervals, node.start, node.end);
+        } else {
+            match &node.children {
+                None => {
+                    HVTree::push_interval(intervals, node.start, node.end);
+                }
+                Some(children) => {
+                    let shift = (31 - level) * 2;
+                    for (i, child) in children.iter().enumerate() {
+                        self.range_intervals(child, high_bits + ((i as u64) << shift), level + 1, bound, intervals);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn interval_count(&self, query: &MBR) -> usize {
+        let bound = match self.encoder.scale_query(query) {
+            Some(bound) => bound,
+            None => return 0,
+        };
+        let mut intervals: Vec<(usize, usize)> = Vec::new();
+        self.range_intervals(&self.root, 0, 0, bound, &mut intervals);
+        intervals.len()
+    }
+
+    pub fn range(&self, query: &MBR) -> Vec<Point> {
+        let bound = match self.encoder.scale_query(query) {
+            Some(bound) => bound,
+            None => return Vec::new(),
+        };
+        let mut intervals: Vec<(usize, usize)> = Vec::new();
+        self.range_intervals(&self.root, 0, 0, bound, &mut intervals);
+
+        let mut res: Vec<Point> = Vec::new();
+        for (start, end) in intervals.iter() {
+            for i in *start..*end {
+                if self.check_bound(i, query) {
+                    res.push(self.points[i].clone());
+                }
+            }
+        }
+        res
+    }
+
+    pub fn range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
+        let bound = match self.encoder.scale_query(query) {
+            Some(bound) => bound,
+            None => return Vec::new(),
+        };
+        let mut intervals: Vec<(usize, usize)> = Vec::new();
+        self.range_intervals(&self.root, 0, 0, bound, &mut intervals);
+        let population: usize = intervals.iter().map(|(start, end)| end - start).sum();
+        if population == 0 {
+            return Vec::new();
+        }
+
+        let weights: Vec<f64> = intervals.iter().map(|(x, y)| (y - x) as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        let mut samples: Vec<Point> = Vec::new();
+        let mut rejections = 0;
+        // For each sample, construct two level sampling.
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let offset = (weights[res] * coin3) as usize + intervals[res].0;
+            if self.check_bound(offset, query) {
+                samples.push(self.points[offset].clone());
+            } else {
+                rejections += 1;
+                let holds_any = || util::holds_any(query, intervals.iter().map(|(start, end)| &self.points[*start..*end]));
+                if util::gives_up(rejections, population, holds_any) { break; }
+            }
+        }
+        samples
+    }
+}
@@ -1,5 +1,6 @@
 use crate::geo::{MBR, Point};
 use crate::util;
+use crate::index::{BuildError, check_param};
 use crate::alias::AliasTable;
 use std::rc::Rc;
 use rand::distributions::{Uniform, Distribution};
@@ -19,13 +20,20 @@ struct KDBTreeNode {
 pub struct KDBTree {
     root: KDBTreeNode,
     data: Vec<Point>,
+    buffer_size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct KDBTreeBuilder {
+    threshold: usize,
+    buffer_size: usize,
 }
 
 impl KDBTreeNode {
-    fn new(points: &mut[Point], level: usize, start: usize, end: usize, bounding_box: MBR) -> KDBTreeNode {
+    fn new(points: &mut[Point], level: usize, start: usize, end: usize, bounding_box: MBR, threshold: usize, buffer_size: usize) -> KDBTreeNode {
         assert_eq!(end - start, points.len());
         let len = points.len();
-        if len < KDBTREE_THRESHOLD {
+        if len < threshold {
             KDBTreeNode {
                 bounding_box: bounding_box,
                 children: None,
@@ -47,14 +55,14 @@ impl KDBTreeNode {
                 left_bounding_box.high.y = split.y;
                 right_bounding_box.low.y = split.y;
             }
-            let left_node = KDBTreeNode::new(&mut points[0..mid], level + 1, start, start + mid, left_bounding_box);
-            let right_node = KDBTreeNode::new(&mut points[mid..len], level + 1, start + mid, end, right_bounding_box);
+            let left_node = KDBTreeNode::new(&mut points[0..mid], level + 1, start, start + mid, left_bounding_box, threshold, buffer_size);
+            let right_node = KDBTreeNode::new(&mut points[mid..len], level + 1, start + mid, end, right_bounding_box, threshold, buffer_size);
             KDBTreeNode {
                 bounding_box: bounding_box,
                 children: Some((Rc::new(left_node), Rc::new(right_node))),
                 start,
                 end,
-                sample_buffer: util::sample_from(points, KDB_SAMPLE_BUFFER_SIZE),
+                sample_buffer: util::sample_from(points, buffer_size),
                 valid_ptr: 0,
             }
         }
@@ -67,14 +75,58 @@ impl KDBTreeNode {
     }
 }
 
+impl KDBTreeBuilder {
+    pub fn new() -> KDBTreeBuilder {
+        KDBTreeBuilder {
+            threshold: KDBTREE_THRESHOLD,
+            buffer_size: KDB_SAMPLE_BUFFER_SIZE,
+        }
+    }
+
+    // Nodes with fewer points become leaves.
+    pub fn threshold(mut self, threshold: usize) -> KDBTreeBuilder {
+        self.threshold = threshold;
+        self
+    }
+
+    // Samples buffered at every internal node.
+    pub fn buffer_size(mut self, buffer_size: usize) -> KDBTreeBuilder {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    // An internal node must hold at least twice as many points as its buffer,
+    // otherwise drawing from the buffer is no cheaper than from the points.
+    pub fn build(&self, data: &[Point]) -> Result<KDBTree, BuildError> {
+        check_param("buffer_size", self.buffer_size, 1)?;
+        check_param("threshold", self.threshold, 2 * self.buffer_size)?;
+        Ok(KDBTree::build(data, self.threshold, self.buffer_size))
+    }
+}
+
+impl Default for KDBTreeBuilder {
+    fn default() -> KDBTreeBuilder {
+        KDBTreeBuilder::new()
+    }
+}
+
 impl KDBTree {
     pub fn from(data: &[Point]) -> KDBTree {
+        KDBTree::build(data, KDBTREE_THRESHOLD, KDB_SAMPLE_BUFFER_SIZE)
+    }
+
+    pub fn builder() -> KDBTreeBuilder {
+        KDBTreeBuilder::new()
+    }
+
+    fn build(data: &[Point], threshold: usize, buffer_size: usize) -> KDBTree {
         let mut points_data: Vec<Point> = Vec::new();
         points_data.extend_from_slice(data);
-        let root = KDBTreeNode::new(&mut points_data, 0, 0, data.len(), MBR::from_points(data));
+        let root = KDBTreeNode::new(&mut points_data, 0, 0, data.len(), MBR::from_points(data), threshold, buffer_size);
         KDBTree {
             root,
             data: points_data,
+            buffer_size,
         }
     }
 
@@ -110,11 +162,18 @@ impl KDBTree {
         let mut frontier: Vec<*mut KDBTreeNode> = Vec::new();
         let mut new_frontier: Vec<*mut KDBTreeNode> = Vec::new();
         let mut weights: Vec<f64> = Vec::new();
-        frontier.push(&mut self.root as *mut KDBTreeNode);
+        if !self.data.is_empty() && self.root.bounding_box.intersects(query) {
+            frontier.push(&mut self.root as *mut KDBTreeNode);
+        }
+        if frontier.is_empty() {
+            return samples;
+        }
         let mut alias = AliasTable::uniform(1);
         let mut rng = util::new_rng();
         let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rejections = 0;
         while samples.len() < k {
+            let accepted = samples.len();
             let coin1 = dist.sample(&mut rng);
             let coin2 = dist.sample(&mut rng);
             let offset = alias.sample(coin1, coin2);
@@ -154,16 +213,56 @@ impl KDBTree {
                             }
                         }
                         //replenish buffer
-                        node.sample_buffer = util::sample_from(&self.data[node.start..node.end], KDB_SAMPLE_BUFFER_SIZE);
+                        node.sample_buffer = util::sample_from(&self.data[node.start..node.end], self.buffer_size);
                         node.valid_ptr = 0;
                     }
                 }
             }
             if flag {
                 std::mem::swap(&mut frontier, &mut new_frontier);
+                if frontier.is_empty() {
+                    return samples;
+                }
                 alias = AliasTable::from(&weights);
             }
+            if samples.len() == accepted {
+                rejections += 1;
+                // The frontier always covers every point of the query.
+                let holds_any = || util::holds_any(query, frontier.iter().map(|node| unsafe { &self.data[(**node).start..(**node).end] }));
+                if util::gives_up(rejections, self.data.len(), holds_any) { break; }
+            }
         }
         samples
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lattice(side: usize) -> Vec<Point> {
+        (0..side * side).map(|i| Point::new((i % side) as f64, (i / side) as f64)).collect()
+    }
+
+    #[test]
+    fn no_samples_without_points() {
+        let mut tree = KDBTree::builder().buffer_size(16).threshold(32).build(&lattice(40)).unwrap();
+        // Between lattice points, across many leaves, and outside of the data.
+        assert!(tree.range_sampling(&MBR::new(&Point::new(3.2, 0.0), &Point::new(3.8, 39.0)), 10).is_empty());
+        assert!(tree.range_sampling(&MBR::new(&Point::new(50.0, 50.0), &Point::new(60.0, 60.0)), 10).is_empty());
+        assert!(KDBTree::from(&[]).range_sampling(&MBR::new(&Point::new(0.0, 0.0), &Point::new(1.0, 1.0)), 10).is_empty());
+    }
+
+    #[test]
+    fn samples_after_buffers_run_out() {
+        let mut tree = KDBTree::builder().buffer_size(16).threshold(32).build(&lattice(40)).unwrap();
+        let query = MBR::new(&Point::new(2.5, 2.5), &Point::new(30.5, 12.5));
+        for _ in 0..3 {
+            let samples = tree.range_sampling(&query, 1000);
+            assert_eq!(samples.len(), 1000);
+            assert!(samples.iter().all(|p| query.contains(p)));
+        }
+        let single = MBR::new(&Point::new(7.0, 7.0), &Point::new(7.0, 7.0));
+        assert_eq!(tree.range_sampling(&single, 50), vec![Point::new(7.0, 7.0); 50]);
+    }
+}
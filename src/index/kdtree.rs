@@ -1,10 +1,16 @@
-use crate::geo::{MBR, Point};
+use crate::geo::{MBR, Point, Region};
+use crate::index::{BuildError, check_param};
 use crate::alias::AliasTable;
 use crate::util;
+use crate::progressive::{OnlineAggregator, StopCondition, Estimate};
 use std::rc::Rc;
 use rand::distributions::{Uniform, Distribution};
 
 const KDTREE_THRESHOLD: usize = 256;
+// Join sampling runs at least this many trials, so that its size estimate
+// holds even for small k, and checks that any pair joins at all if none of
+// them is accepted.
+const JOIN_MIN_TRIALS: usize = 1000;
 // Should maintain balance up to roughly 1/20
 // const KDTREE_MED_SAMPLE: usize = 200;
 
@@ -13,68 +19,214 @@ struct KDTreeNode {
     children: Option<(Rc<KDTreeNode>, Rc<KDTreeNode>)>,
     start: usize,
     end: usize,
+    // Points per category, empty unless the tree is built with categories.
+    counts: Vec<usize>,
 }
 
 pub struct KDTree {
     root: KDTreeNode,
     data: Vec<Point>,
+    // Position in the input of every point in data, kept only by trees built
+    // with categories.
+    ids: Option<Vec<usize>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct KDTreeBuilder {
+    threshold: usize,
+}
+
+// Input and parameters shared by the whole construction with categories.
+struct KDTreeInput<'a> {
+    data: &'a [Point],
+    // Category of every input point and the number of categories.
+    categories: &'a [usize],
+    num_categories: usize,
+    threshold: usize,
+}
+
+// Coordinate of a point along the split axis of a level, x on even levels.
+fn coordinate(p: &Point, level: usize) -> f64 {
+    match level % 2 {
+        0 => p.x,
+        _ => p.y,
+    }
+}
+
+// Bounding boxes of the children of a node split at a coordinate.
+fn split_box(bounding_box: &MBR, level: usize, value: f64) -> (MBR, MBR) {
+    let mut left_bounding_box = bounding_box.clone();
+    let mut right_bounding_box = bounding_box.clone();
+    match level % 2 {
+        0 => {
+            left_bounding_box.high.x = value;
+            right_bounding_box.low.x = value;
+        }
+        _ => {
+            left_bounding_box.high.y = value;
+            right_bounding_box.low.y = value;
+        }
+    }
+    (left_bounding_box, right_bounding_box)
 }
 
 impl KDTreeNode {
-    fn new(points: &mut[Point], level: usize, start: usize, end: usize, bounding_box: MBR) -> KDTreeNode {
+    fn new(points: &mut [Point], threshold: usize, level: usize, start: usize, end: usize, bounding_box: MBR) -> KDTreeNode {
         assert_eq!(end - start, points.len());
         let len = points.len();
-        if len < KDTREE_THRESHOLD {
+        if len < threshold {
             KDTreeNode {
-                bounding_box: bounding_box,
+                bounding_box,
                 children: None,
                 start,
                 end,
+                counts: Vec::new(),
             }
         } else {
             let mid = len / 2;
-            let mut left_bounding_box = bounding_box.clone();
-            let mut right_bounding_box = bounding_box.clone();
-            if level % 2 == 0 {
-                let split = order_stat::kth_by(points, mid, |p1, p2| p1.x.partial_cmp(&p2.x).unwrap());
-                left_bounding_box.high.x = split.x;
-                right_bounding_box.low.x = split.x;
-            } else {
-                let split = order_stat::kth_by(points, mid, |p1, p2| p1.y.partial_cmp(&p2.y).unwrap());
-                left_bounding_box.high.y = split.y;
-                right_bounding_box.low.y = split.y;
+            let split = order_stat::kth_by(points, mid, |p1, p2| coordinate(p1, level).partial_cmp(&coordinate(p2, level)).unwrap());
+            let (left_bounding_box, right_bounding_box) = split_box(&bounding_box, level, coordinate(split, level));
+            let left_node = KDTreeNode::new(&mut points[0..mid], threshold, level + 1, start, start + mid, left_bounding_box);
+            let right_node = KDTreeNode::new(&mut points[mid..len], threshold, level + 1, start + mid, end, right_bounding_box);
+            KDTreeNode {
+                bounding_box,
+                children: Some((Rc::new(left_node), Rc::new(right_node))),
+                start,
+                end,
+                counts: Vec::new(),
+            }
+        }
+    }
+
+    // Splits input positions, so that leaves can group their points by
+    // category and every node counts its points per category.
+    fn with_categories(input: &KDTreeInput, ids: &mut [usize], level: usize, start: usize, end: usize, bounding_box: MBR) -> KDTreeNode {
+        assert_eq!(end - start, ids.len());
+        let data = input.data;
+        let len = ids.len();
+        if len < input.threshold {
+            // Points of a leaf are grouped by category, so that the points of
+            // one category form a contiguous run.
+            ids.sort_by_key(|id| input.categories[*id]);
+            let mut counts: Vec<usize> = vec![0; input.num_categories];
+            for id in ids.iter() {
+                counts[input.categories[*id]] += 1;
+            }
+            KDTreeNode {
+                bounding_box,
+                children: None,
+                start,
+                end,
+                counts,
             }
-            let left_node = KDTreeNode::new(&mut points[0..mid], level + 1, start, start + mid, left_bounding_box);
-            let right_node = KDTreeNode::new(&mut points[mid..len], level + 1, start + mid, end, right_bounding_box);
+        } else {
+            let mid = len / 2;
+            let split = order_stat::kth_by(ids, mid, |i1, i2| coordinate(&data[*i1], level).partial_cmp(&coordinate(&data[*i2], level)).unwrap());
+            let (left_bounding_box, right_bounding_box) = split_box(&bounding_box, level, coordinate(&data[*split], level));
+            let left_node = KDTreeNode::with_categories(input, &mut ids[0..mid], level + 1, start, start + mid, left_bounding_box);
+            let right_node = KDTreeNode::with_categories(input, &mut ids[mid..len], level + 1, start + mid, end, right_bounding_box);
+            let counts: Vec<usize> = left_node.counts.iter().zip(right_node.counts.iter()).map(|(l, r)| l + r).collect();
             KDTreeNode {
-                bounding_box: bounding_box,
+                bounding_box,
                 children: Some((Rc::new(left_node), Rc::new(right_node))),
                 start,
                 end,
+                counts,
             }
         }
     }
 
     fn size(&self) -> usize {
-        48 + if let Some((left, right)) = &self.children {
+        48 + self.counts.len() * 8 + if let Some((left, right)) = &self.children {
             16 + left.size() + right.size()
         } else { 0 }
     }
+
+    // Offset of the run of a category within a leaf.
+    fn category_start(&self, category: usize) -> usize {
+        self.start + self.counts[..category].iter().sum::<usize>()
+    }
+}
+
+impl KDTreeBuilder {
+    pub fn new() -> KDTreeBuilder {
+        KDTreeBuilder {
+            threshold: KDTREE_THRESHOLD,
+        }
+    }
+
+    // Nodes with fewer points become leaves.
+    pub fn threshold(mut self, threshold: usize) -> KDTreeBuilder {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn build(&self, data: &[Point]) -> Result<KDTree, BuildError> {
+        check_param("threshold", self.threshold, 2)?;
+        Ok(KDTree::build(data, self.threshold))
+    }
+
+    pub fn build_with_categories(&self, data: &[Point], categories: &[usize]) -> Result<KDTree, BuildError> {
+        check_param("threshold", self.threshold, 2)?;
+        let num_categories = num_categories(data, categories)?;
+        Ok(KDTree::build_with_categories(KDTreeInput { data, categories, num_categories, threshold: self.threshold }))
+    }
+}
+
+impl Default for KDTreeBuilder {
+    fn default() -> KDTreeBuilder {
+        KDTreeBuilder::new()
+    }
+}
+
+fn num_categories(data: &[Point], categories: &[usize]) -> Result<usize, BuildError> {
+    if categories.len() != data.len() {
+        return Err(BuildError::LengthMismatch("categories", categories.len(), data.len()));
+    }
+    Ok(categories.iter().max().map_or(0, |c| c + 1))
 }
 
 impl KDTree {
     pub fn from(data: &[Point]) -> KDTree {
+        KDTree::build(data, KDTREE_THRESHOLD)
+    }
+
+    pub fn builder() -> KDTreeBuilder {
+        KDTreeBuilder::new()
+    }
+
+    // Also keeps per-node counts of every category in 0..num_categories, for
+    // category_range_sampling, and the input positions of the points, for
+    // filtered_range_sampling. Panics unless there is one category per point.
+    pub fn with_categories(data: &[Point], categories: &[usize]) -> KDTree {
+        let num_categories = num_categories(data, categories).unwrap();
+        KDTree::build_with_categories(KDTreeInput { data, categories, num_categories, threshold: KDTREE_THRESHOLD })
+    }
+
+    fn build(data: &[Point], threshold: usize) -> KDTree {
         let mut points_data: Vec<Point> = Vec::new();
-        points_data.extend_from_slice(&data);
-        let root = KDTreeNode::new(&mut points_data, 0, 0, data.len(), MBR::from_points(data));
+        points_data.extend_from_slice(data);
+        let root = KDTreeNode::new(&mut points_data, threshold, 0, 0, data.len(), MBR::from_points(data));
         KDTree {
             root,
             data: points_data,
+            ids: None,
+        }
+    }
+
+    fn build_with_categories(input: KDTreeInput) -> KDTree {
+        let data = input.data;
+        let mut ids: Vec<usize> = (0..data.len()).collect();
+        let root = KDTreeNode::with_categories(&input, &mut ids, 0, 0, data.len(), MBR::from_points(data));
+        KDTree {
+            root,
+            data: ids.iter().map(|id| data[*id].clone()).collect(),
+            ids: Some(ids),
         }
     }
 
     pub fn size(&self) -> usize {
-        &self.data.len() * 16 + self.root.size()
+        &self.data.len() * 16 + self.ids.as_ref().map_or(0, |ids| ids.len() * 8) + self.root.size()
     }
 
     pub fn range(&self, query: &MBR) -> Vec<Point> {
@@ -100,9 +252,21 @@ impl KDTree {
         res
     }
 
+    pub fn range_count(&self, query: &MBR) -> usize {
+        let mut res: usize = 0;
+        for node in self.candidates(query).iter() {
+            if query.contains_mbr(&node.bounding_box) {
+                res += node.end - node.start;
+            } else {
+                res += self.data[node.start..node.end].iter().filter(|p| query.contains(p)).count();
+            }
+        }
+        res
+    }
+
     pub fn olken_range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
         let mut samples: Vec<Point> = Vec::new();
-        if !query.intersects(&self.root.bounding_box) {
+        if self.data.is_empty() || !query.intersects(&self.root.bounding_box) {
             return samples;
         }
         let mut lca_root: &KDTreeNode = &self.root;
@@ -128,7 +292,9 @@ impl KDTree {
 
         let mut rng = util::new_rng();
         let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rejections = 0;
         while samples.len() < k {
+            let accepted = samples.len();
             let mut now: &KDTreeNode = &self.root;
             loop {
                 match &now.children {
@@ -152,6 +318,10 @@ impl KDTree {
                     }
                 }
             }
+            if samples.len() == accepted {
+                rejections += 1;
+                if util::gives_up(rejections, self.data.len(), || self.range_count(query) > 0) { break; }
+            }
         }
         samples
     }
@@ -184,12 +354,17 @@ impl KDTree {
                 }
             }
         }
+        let population: usize = candidates.iter().map(|node| node.end - node.start).sum();
+        if population == 0 {
+            return samples;
+        }
         // Construct top level alias structure
         //let mut weights = vec![spare.len() as f64];
         let weights: Vec<f64> = candidates.iter().map(|node| (node.end - node.start) as f64).collect();
         let dist = Uniform::from(0.0f64..1.0f64);
         let mut rng = util::new_rng();
         let top_level_alias = AliasTable::from(&weights);
+        let mut rejections = 0;
 
         // For each sample, construct two level sampling.
         while samples.len() < k {
@@ -200,10 +375,424 @@ impl KDTree {
             let offset = (weights[res] * coin3) as usize  + candidates[res].start;
             if query.contains(&self.data[offset]) {
                 samples.push(self.data[offset].clone());
+            } else {
+                rejections += 1;
+                if util::gives_up(rejections, population, || self.range_count(query) > 0) { break; }
             }
         }
 
         samples
     }
 
-}
\ No newline at end of file
+    // Two level sampling over any region, e.g. a union of rectangles: every
+    // node is a candidate at most once and a point is accepted if the region
+    // contains it, so points in overlaps are not over-represented.
+    pub fn region_range_sampling(&self, region: &dyn Region, k: usize) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        let candidates = self.candidates(region);
+        let population: usize = candidates.iter().map(|node| node.end - node.start).sum();
+        if population == 0 {
+            return samples;
+        }
+        let weights: Vec<f64> = candidates.iter().map(|node| (node.end - node.start) as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        let mut rejections = 0;
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let offset = (weights[res] * coin3) as usize + candidates[res].start;
+            if region.contains(&self.data[offset]) {
+                samples.push(self.data[offset].clone());
+            } else {
+                rejections += 1;
+                let holds_any = || util::holds_any(region, candidates.iter().map(|node| &self.data[node.start..node.end]));
+                if util::gives_up(rejections, population, holds_any) { break; }
+            }
+        }
+        samples
+    }
+
+    // Two level sampling that also rejects points whose input position fails
+    // the predicate, e.g. a filter on their payload. Only trees built with
+    // categories keep input positions; others return no samples.
+    pub fn filtered_range_sampling(&self, query: &MBR, k: usize, predicate: &dyn Fn(usize) -> bool) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        let ids = match &self.ids {
+            Some(ids) => ids,
+            None => return samples,
+        };
+        let candidates = self.candidates(query);
+        let population: usize = candidates.iter().map(|node| node.end - node.start).sum();
+        if population == 0 {
+            return samples;
+        }
+        let accepts = |offset: usize| query.contains(&self.data[offset]) && predicate(ids[offset]);
+        let weights: Vec<f64> = candidates.iter().map(|node| (node.end - node.start) as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        let mut rejections = 0;
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let offset = (weights[res] * coin3) as usize + candidates[res].start;
+            if accepts(offset) {
+                samples.push(self.data[offset].clone());
+            } else {
+                rejections += 1;
+                let holds_any = || candidates.iter().any(|node| (node.start..node.end).any(accepts));
+                if util::gives_up(rejections, population, holds_any) { break; }
+            }
+        }
+        samples
+    }
+
+    // Samples points of one category only, weighting candidates by their
+    // count of that category so that rare categories are not rejected. Within
+    // a covered node the sample descends by category counts to a leaf. Trees
+    // built without categories count none and return no samples.
+    pub fn category_range_sampling(&self, query: &MBR, category: usize, k: usize) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        if category >= self.root.counts.len() {
+            return samples;
+        }
+        let candidates: Vec<&KDTreeNode> = self.candidates(query).into_iter().filter(|node| node.counts[category] > 0).collect();
+        // Candidates not covered by the query are leaves, where the category
+        // is a contiguous run.
+        let holds_category = candidates.iter().any(|node| query.contains_mbr(&node.bounding_box) || {
+            let start = node.category_start(category);
+            util::holds_any(query, Some(&self.data[start..start + node.counts[category]]))
+        });
+        if !holds_category {
+            return samples;
+        }
+        let weights: Vec<f64> = candidates.iter().map(|node| node.counts[category] as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let mut now: &KDTreeNode = candidates[top_level_alias.sample(coin1, coin2)];
+            while let Some((left, right)) = &now.children {
+                let p = left.counts[category] as f64 / now.counts[category] as f64;
+                if dist.sample(&mut rng) < p { now = left; }
+                else { now = right; }
+            }
+            let offset = (dist.sample(&mut rng) * now.counts[category] as f64) as usize + now.category_start(category);
+            if query.contains(&self.data[offset]) {
+                samples.push(self.data[offset].clone());
+            }
+        }
+        samples
+    }
+
+    // Online aggregation over the two level sampler: after every `batch`
+    // trials the running count and mean of `value` are passed to `report`,
+    // until `stop` is reached. Returns the final estimate.
+    pub fn progressive_range_sampling(&self, query: &MBR, batch: usize, stop: &StopCondition,
+                                      value: &dyn Fn(&Point) -> f64, report: &mut dyn FnMut(&Estimate)) -> Estimate {
+        assert!(batch > 0);
+        let candidates = self.candidates(query);
+        let weights: Vec<f64> = candidates.iter().map(|node| (node.end - node.start) as f64).collect();
+        let mut aggregator = OnlineAggregator::new(weights.iter().sum());
+        if !util::holds_any(query, candidates.iter().map(|node| &self.data[node.start..node.end])) {
+            let estimate = aggregator.estimate();
+            report(&estimate);
+            return estimate;
+        }
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        loop {
+            for _ in 0..batch {
+                let coin1 = dist.sample(&mut rng);
+                let coin2 = dist.sample(&mut rng);
+                let res = top_level_alias.sample(coin1, coin2);
+                let coin3 = dist.sample(&mut rng);
+                let sample = &self.data[(weights[res] * coin3) as usize + candidates[res].start];
+                if query.contains(sample) {
+                    aggregator.accept(value(sample));
+                } else {
+                    aggregator.reject();
+                }
+            }
+            let estimate = aggregator.estimate();
+            report(&estimate);
+            if stop.reached(&estimate) {
+                return estimate;
+            }
+        }
+    }
+
+    fn candidates<'a>(&'a self, query: &dyn Region) -> Vec<&'a KDTreeNode> {
+        let mut candidates: Vec<&KDTreeNode> = Vec::new();
+        let mut stack: Vec<&KDTreeNode> = Vec::new();
+        if query.intersects(&self.root.bounding_box) { stack.push(&self.root); }
+        while let Some(now) = stack.pop() {
+            if query.contains_mbr(&now.bounding_box) {
+                candidates.push(now);
+            } else {
+                match &now.children {
+                    None => { candidates.push(now); }
+                    Some((left, right)) => {
+                        if query.intersects(&left.bounding_box) { stack.push(left); }
+                        if query.intersects(&right.bounding_box) { stack.push(right); }
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    // Samples k uniform pairs (p, q) with p from this tree, q from `other` and
+    // dist(p, q) <= eps, and estimates the size of the whole join.
+    //
+    // Each leaf of this tree collects the nodes of `other` near its bounding
+    // box; each point p then keeps the nodes within eps of it, which bound
+    // its partners by W(p). A trial draws p with probability W(p) / sum(W), a
+    // uniform point of those nodes, and accepts it if it is within eps, so
+    // accepted pairs are uniform over the join and sum(W) times the
+    // acceptance rate estimates its size.
+    pub fn join_sampling(&self, other: &KDTree, eps: f64, k: usize) -> (Vec<(Point, Point)>, f64) {
+        let eps2 = eps * eps;
+        let mut leaves: Vec<(&KDTreeNode, Vec<&KDTreeNode>)> = Vec::new();
+        let mut stack: Vec<&KDTreeNode> = vec![&self.root];
+        while let Some(now) = stack.pop() {
+            match &now.children {
+                None => {
+                    if now.end > now.start {
+                        let bounding_box = MBR::from_points(&self.data[now.start..now.end]);
+                        let expanded = MBR::new(&Point::new(bounding_box.low.x - eps, bounding_box.low.y - eps),
+                                                &Point::new(bounding_box.high.x + eps, bounding_box.high.y + eps));
+                        leaves.push((now, other.candidates(&expanded)));
+                    }
+                }
+                Some((left, right)) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        leaves.sort_unstable_by_key(|(leaf, _)| leaf.start);
+
+        let mut weights: Vec<f64> = vec![0.0; self.data.len()];
+        for (leaf, candidates) in leaves.iter() {
+            for (weight, p) in weights[leaf.start..leaf.end].iter_mut().zip(self.data[leaf.start..leaf.end].iter()) {
+                *weight = candidates.iter().filter(|node| p.min_dist_mbr(&node.bounding_box) <= eps2)
+                                           .map(|node| (node.end - node.start) as f64).sum();
+            }
+        }
+        let total_weight: f64 = weights.iter().sum();
+        let mut samples: Vec<(Point, Point)> = Vec::new();
+        if total_weight == 0.0 {
+            return (samples, 0.0);
+        }
+
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        let (mut trials, mut accepted) = (0usize, 0usize);
+        while samples.len() < k || trials < JOIN_MIN_TRIALS {
+            if trials == JOIN_MIN_TRIALS && accepted == 0 && !self.joins_any(other, &leaves, eps) {
+                return (samples, 0.0);
+            }
+            trials += 1;
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let offset = top_level_alias.sample(coin1, coin2);
+            let p = &self.data[offset];
+            let leaf = leaves.partition_point(|(leaf, _)| leaf.end <= offset);
+            let mut coin3 = dist.sample(&mut rng) * weights[offset];
+            for node in leaves[leaf].1.iter() {
+                if p.min_dist_mbr(&node.bounding_box) > eps2 {
+                    continue;
+                }
+                let count = (node.end - node.start) as f64;
+                if coin3 < count {
+                    let q = &other.data[node.start + (coin3 as usize).min(node.end - node.start - 1)];
+                    if p.min_dist(q) <= eps {
+                        accepted += 1;
+                        if samples.len() < k {
+                            samples.push((p.clone(), q.clone()));
+                        }
+                    }
+                    break;
+                }
+                coin3 -= count;
+            }
+        }
+        (samples, total_weight * accepted as f64 / trials as f64)
+    }
+
+    // Whether some point of a leaf is within eps of a point of its nodes.
+    fn joins_any(&self, other: &KDTree, leaves: &[(&KDTreeNode, Vec<&KDTreeNode>)], eps: f64) -> bool {
+        leaves.iter().any(|(leaf, candidates)| self.data[leaf.start..leaf.end].iter().any(|p| {
+            candidates.iter().any(|node| p.min_dist_mbr(&node.bounding_box) <= eps * eps &&
+                other.data[node.start..node.end].iter().any(|q| p.min_dist(q) <= eps))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::{MBRDifference, MultiMBR};
+
+    fn lattice(side: usize, offset: f64) -> Vec<Point> {
+        (0..side * side).map(|i| Point::new((i % side) as f64 + offset, (i / side) as f64 + offset)).collect()
+    }
+
+    #[test]
+    fn join_without_pairs_within_eps() {
+        // The box of `other` holds the point of this tree, but both of its
+        // points are 1.1 away from it.
+        let tree = KDTree::from(&[Point::new(0.0, 0.0)]);
+        let other = KDTree::from(&[Point::new(-1.1, 0.0), Point::new(1.1, 0.0)]);
+        let (samples, estimated_size) = tree.join_sampling(&other, 1.0, 10);
+        assert!(samples.is_empty());
+        assert_eq!(estimated_size, 0.0);
+        // Diagonal neighbours of a lattice shifted by half a cell are 0.707
+        // away, more than eps, while the leaf boxes overlap.
+        let (samples, estimated_size) = KDTree::from(&lattice(30, 0.0)).join_sampling(&KDTree::from(&lattice(30, 0.5)), 0.7, 10);
+        assert!(samples.is_empty());
+        assert_eq!(estimated_size, 0.0);
+    }
+
+    #[test]
+    fn join_estimate_without_samples() {
+        let (samples, estimated_size) = KDTree::from(&lattice(30, 0.0)).join_sampling(&KDTree::from(&lattice(30, 0.5)), 0.75, 0);
+        assert!(samples.is_empty());
+        assert!(estimated_size > 0.0);
+    }
+
+    #[test]
+    fn join_estimate_matches_brute_force() {
+        let data = lattice(40, 0.0);
+        let other: Vec<Point> = lattice(40, 0.0).iter().map(|p| Point::new(p.x * 0.9 + 0.3, p.y * 1.1 - 0.2)).collect();
+        let eps = 1.5;
+        let exact = data.iter().map(|p| other.iter().filter(|q| p.min_dist(q) <= eps).count()).sum::<usize>() as f64;
+        let (samples, estimated_size) = KDTree::from(&data).join_sampling(&KDTree::from(&other), eps, 5000);
+        assert_eq!(samples.len(), 5000);
+        assert!(samples.iter().all(|(p, q)| p.min_dist(q) <= eps));
+        assert!((estimated_size - exact).abs() <= 0.1 * exact, "estimated {} for {} pairs", estimated_size, exact);
+    }
+
+    #[test]
+    fn union_samples_do_not_favour_overlaps() {
+        let data = lattice(40, 0.0);
+        let tree = KDTree::from(&data);
+        // 700 points in the union, 100 of them in both rectangles.
+        let union = MultiMBR::new(&[MBR::new(&Point::new(0.5, 0.5), &Point::new(20.5, 20.5)),
+                                    MBR::new(&Point::new(10.5, 10.5), &Point::new(30.5, 30.5))]);
+        let samples = tree.region_range_sampling(&union, 20000);
+        assert_eq!(samples.len(), 20000);
+        assert!(samples.iter().all(|p| union.contains(p)));
+        let overlap = samples.iter().filter(|p| p.x > 10.0 && p.x < 21.0 && p.y > 10.0 && p.y < 21.0).count();
+        assert!((overlap as f64 / 20000.0 - 1.0 / 7.0).abs() < 0.015, "{} samples in the overlap", overlap);
+        // Both rectangles lie between lattice points.
+        let empty = MultiMBR::new(&[MBR::new(&Point::new(3.2, 0.0), &Point::new(3.8, 39.0)),
+                                    MBR::new(&Point::new(0.0, 7.1), &Point::new(39.0, 7.9))]);
+        assert!(tree.region_range_sampling(&empty, 10).is_empty());
+    }
+
+    #[test]
+    fn difference_samples_avoid_holes() {
+        let tree = KDTree::from(&lattice(40, 0.0));
+        let holes = MBRDifference::new(&MBR::new(&Point::new(4.5, 4.5), &Point::new(12.5, 12.5)),
+                                       &[MBR::new(&Point::new(5.5, 5.5), &Point::new(9.5, 9.5)),
+                                         MBR::new(&Point::new(8.5, 10.5), &Point::new(13.0, 13.0))]);
+        let mut seen: Vec<(i64, i64)> = tree.region_range_sampling(&holes, 5000).iter().map(|p| {
+            assert!(holes.contains(p));
+            (p.x as i64, p.y as i64)
+        }).collect();
+        seen.sort_unstable();
+        seen.dedup();
+        // 64 points in the include, 16 and 8 of them excluded.
+        assert_eq!(seen.len(), 40);
+        let covered = MBRDifference::new(&MBR::new(&Point::new(4.5, 4.5), &Point::new(12.5, 12.5)),
+                                         &[MBR::new(&Point::new(4.0, 4.0), &Point::new(13.0, 13.0))]);
+        assert!(tree.region_range_sampling(&covered, 10).is_empty());
+    }
+
+    #[test]
+    fn filtered_samples_keep_input_positions() {
+        let data = lattice(30, 0.0);
+        let tree = KDTree::with_categories(&data, &vec![0; data.len()]);
+        let query = MBR::new(&Point::new(2.5, 2.5), &Point::new(25.5, 20.5));
+        let samples = tree.filtered_range_sampling(&query, 500, &|id| id % 7 == 0);
+        assert_eq!(samples.len(), 500);
+        for p in samples.iter() {
+            assert!(query.contains(p));
+            assert_eq!((p.y as usize * 30 + p.x as usize) % 7, 0);
+        }
+        // No point of the query passes the predicate.
+        assert!(tree.filtered_range_sampling(&query, 10, &|id| id < 30).is_empty());
+        // Plain trees keep no input positions.
+        assert!(KDTree::from(&data).filtered_range_sampling(&query, 10, &|_| true).is_empty());
+    }
+
+    #[test]
+    fn category_samples_on_empty_runs() {
+        let data = lattice(30, 0.0);
+        let categories: Vec<usize> = (0..data.len()).map(|i| if i % 30 < 3 { 1 } else { 0 }).collect();
+        let tree = KDTree::with_categories(&data, &categories);
+        let samples = tree.category_range_sampling(&MBR::new(&Point::new(0.5, 0.5), &Point::new(10.5, 10.5)), 1, 200);
+        assert_eq!(samples.len(), 200);
+        assert!(samples.iter().all(|p| p.x >= 1.0 && p.x <= 2.0));
+        // Leaves in the query hold the category, but not within it.
+        assert!(tree.category_range_sampling(&MBR::new(&Point::new(3.5, 0.5), &Point::new(10.5, 10.5)), 1, 10).is_empty());
+        assert!(tree.category_range_sampling(&MBR::new(&Point::new(0.5, 0.5), &Point::new(10.5, 10.5)), 2, 10).is_empty());
+        assert!(KDTree::from(&data).category_range_sampling(&MBR::new(&Point::new(0.5, 0.5), &Point::new(10.5, 10.5)), 0, 10).is_empty());
+    }
+
+    #[test]
+    fn progressive_on_empty_range() {
+        // The query lies between lattice points, so only a target error is
+        // left to stop on and it is never reached.
+        let tree = KDTree::from(&lattice(30, 0.0));
+        let query = MBR::new(&Point::new(10.2, 10.2), &Point::new(10.8, 12.8));
+        let mut reports = 0;
+        let estimate = tree.progressive_range_sampling(&query, 100, &StopCondition::target_error(0.05), &|p| p.x, &mut |_| reports += 1);
+        assert_eq!(estimate.samples, 0);
+        assert_eq!(estimate.count, 0.0);
+        assert_eq!(reports, 1);
+    }
+
+    #[test]
+    fn progressive_stops_at_sample_cap() {
+        let tree = KDTree::from(&lattice(30, 0.0));
+        let query = MBR::new(&Point::new(4.5, 4.5), &Point::new(20.5, 25.5));
+        let mut reports = 0;
+        let estimate = tree.progressive_range_sampling(&query, 100, &StopCondition::max_samples(500), &|p| p.x, &mut |_| reports += 1);
+        assert!(estimate.samples >= 500 && estimate.samples < 600);
+        assert_eq!(reports * 100, estimate.trials);
+    }
+
+    #[test]
+    fn progressive_intervals_cover_the_truth() {
+        let data = lattice(40, 0.0);
+        let tree = KDTree::from(&data);
+        let query = MBR::new(&Point::new(3.5, 7.5), &Point::new(31.5, 22.5));
+        let inside: Vec<&Point> = data.iter().filter(|p| query.contains(p)).collect();
+        let count = inside.len() as f64;
+        let mean = inside.iter().map(|p| p.x).sum::<f64>() / count;
+        // Both 95% intervals miss the truth in about 1 of 20 runs; 10 misses
+        // out of 50 are all but impossible for intervals of that level.
+        let runs = 50;
+        let (mut count_covered, mut mean_covered) = (0, 0);
+        for _ in 0..runs {
+            let estimate = tree.progressive_range_sampling(&query, 50, &StopCondition::max_samples(400), &|p| p.x, &mut |_| {});
+            if (estimate.count - count).abs() <= estimate.count_error { count_covered += 1; }
+            if (estimate.mean - mean).abs() <= estimate.mean_error { mean_covered += 1; }
+        }
+        assert!(count_covered > runs - 10, "count covered in {} of {} runs", count_covered, runs);
+        assert!(mean_covered > runs - 10, "mean covered in {} of {} runs", mean_covered, runs);
+    }
+}
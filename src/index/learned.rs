@@ -0,0 +1,298 @@
+use crate::alias::AliasTable;
+use crate::geo::{MBR, Point, GridEncoder, EncodeError};
+use crate::index::zvtree::zrange_intervals;
+use crate::util;
+use crate::index::{BuildError, check_param};
+use superslice::*;
+use rand::distributions::{Uniform, Distribution};
+
+// Maximum distance between a predicted and the real position of a key.
+const LEARNED_MAX_ERROR: usize = 32;
+// Interval budget of the z-value decomposition of a query.
+const LEARNED_MAX_INTERVALS: usize = 1024;
+
+struct Segment {
+    key: u64,
+    slope: f64,
+    intercept: f64,
+}
+
+// Sorted z-values without a tree: a piecewise-linear model of their CDF
+// predicts the position of a key, and the search is confined to a window of
+// max_error positions around the prediction.
+pub struct LearnedZVIndex {
+    segments: Vec<Segment>,
+    data: Vec<u64>,
+    points: Vec<Point>,
+    encoder: GridEncoder,
+    max_error: usize,
+    max_intervals: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct LearnedZVIndexBuilder {
+    max_error: usize,
+    max_intervals: usize,
+    encoder: Option<GridEncoder>,
+}
+
+impl LearnedZVIndexBuilder {
+    pub fn new() -> LearnedZVIndexBuilder {
+        LearnedZVIndexBuilder {
+            max_error: LEARNED_MAX_ERROR,
+            max_intervals: LEARNED_MAX_INTERVALS,
+            encoder: None,
+        }
+    }
+
+    pub fn max_error(mut self, max_error: usize) -> LearnedZVIndexBuilder {
+        self.max_error = max_error;
+        self
+    }
+
+    pub fn max_intervals(mut self, max_intervals: usize) -> LearnedZVIndexBuilder {
+        self.max_intervals = max_intervals;
+        self
+    }
+
+    // Defaults to an encoder fitted to the input.
+    pub fn encoder(mut self, encoder: GridEncoder) -> LearnedZVIndexBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    pub fn build(&self, input: &[Point]) -> Result<LearnedZVIndex, BuildError> {
+        check_param("max_intervals", self.max_intervals, 1)?;
+        let encoder = self.encoder.clone().unwrap_or_else(|| GridEncoder::fit(input));
+        LearnedZVIndex::build(input, encoder, self.max_error, self.max_intervals).map_err(BuildError::from)
+    }
+}
+
+impl Default for LearnedZVIndexBuilder {
+    fn default() -> LearnedZVIndexBuilder {
+        LearnedZVIndexBuilder::new()
+    }
+}
+
+impl LearnedZVIndex {
+    pub fn from(input: &[Point]) -> LearnedZVIndex {
+        LearnedZVIndex::with_encoder(input, GridEncoder::fit(input)).expect("Expect finite coordinates")
+    }
+
+    pub fn builder() -> LearnedZVIndexBuilder {
+        LearnedZVIndexBuilder::new()
+    }
+
+    pub fn with_encoder(input: &[Point], encoder: GridEncoder) -> Result<LearnedZVIndex, EncodeError> {
+        LearnedZVIndex::build(input, encoder, LEARNED_MAX_ERROR, LEARNED_MAX_INTERVALS)
+    }
+
+    fn build(input: &[Point], encoder: GridEncoder, max_error: usize, max_intervals: usize) -> Result<LearnedZVIndex, EncodeError> {
+        let mut entries: Vec<(u64, Point)> = Vec::new();
+        for p in input.iter() {
+            entries.push((encoder.to_zvalue(p)?, p.clone()));
+        }
+        entries.sort_unstable_by_key(|e| e.0);
+        let (data, points): (Vec<u64>, Vec<Point>) = entries.into_iter().unzip();
+        Ok(LearnedZVIndex {
+            segments: LearnedZVIndex::fit(&data, max_error),
+            data,
+            points,
+            encoder,
+            max_error,
+            max_intervals,
+        })
+    }
+
+    // Greedy shrinking cone: extend the current segment while some slope keeps
+    // every key's first position within the error bound.
+    fn fit(data: &[u64], max_error: usize) -> Vec<Segment> {
+        let mut segments: Vec<Segment> = Vec::new();
+        let eps = max_error as f64;
+        let mut start: Option<(u64, f64)> = None;
+        let (mut slope_low, mut slope_high) = (0.0_f64, f64::INFINITY);
+        for (i, key) in data.iter().enumerate() {
+            if i > 0 && data[i - 1] == *key {
+                continue;
+            }
+            let pos = i as f64;
+            if let Some((start_key, start_pos)) = start {
+                let dk = (key - start_key) as f64;
+                let low = (pos - eps - start_pos) / dk;
+                let high = (pos + eps - start_pos) / dk;
+                if low <= slope_high && high >= slope_low {
+                    slope_low = slope_low.max(low);
+                    slope_high = slope_high.min(high);
+                    continue;
+                }
+                segments.push(Segment {
+                    key: start_key,
+                    slope: if slope_high.is_finite() { (slope_low + slope_high) / 2.0 } else { 0.0 },
+                    intercept: start_pos,
+                });
+            }
+            start = Some((*key, pos));
+            slope_low = 0.0;
+            slope_high = f64::INFINITY;
+        }
+        if let Some((start_key, start_pos)) = start {
+            segments.push(Segment {
+                key: start_key,
+                slope: if slope_high.is_finite() { (slope_low + slope_high) / 2.0 } else { 0.0 },
+                intercept: start_pos,
+            });
+        }
+        segments
+    }
+
+    pub fn encoder(&self) -> &GridEncoder {
+        &self.encoder
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len() * 24 + self.segments.len() * 24 + 40
+    }
+
+    fn predict(&self, key: u64) -> usize {
+        let idx = self.segments.upper_bound_by_key(&key, |s| s.key);
+        if idx == 0 {
+            return 0;
+        }
+        let segment = &self.segments[idx - 1];
+        let pos = segment.intercept + segment.slope * (key - segment.key) as f64;
+        (pos.max(0.0) as usize).min(self.data.len())
+    }
+
+    fn lower_bound(&self, key: u64) -> usize {
+        let guess = self.predict(key);
+        let low = guess.saturating_sub(self.max_error + 1);
+        let high = (guess + self.max_error + 2).min(self.data.len());
+        // Keys absent from the data may fall outside of the window, e.g. after
+        // a long run of duplicates; fall back to a full binary search then.
+        if (low == 0 || self.data[low - 1] < key) && (high == self.data.len() || self.data[high] >= key) {
+            low + self.data[low..high].lower_bound(&key)
+        } else {
+            self.data.lower_bound(&key)
+        }
+    }
+
+    fn upper_bound(&self, key: u64) -> usize {
+        if key == u64::MAX { self.data.len() } else { self.lower_bound(key + 1) }
+    }
+
+    fn range_intervals(&self, query: &MBR) -> Vec<(usize, usize)> {
+        match self.encoder.scale_query(query) {
+            Some(bound) => zrange_intervals(bound, self.max_intervals,
+                &|zmin, zmax| (self.lower_bound(zmin), self.upper_bound(zmax))),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn range(&self, query: &MBR) -> Vec<Point> {
+        let mut res: Vec<Point> = Vec::new();
+        for (start, end) in self.range_intervals(query).iter() {
+            for p in self.points[*start..*end].iter() {
+                if query.contains(p) {
+                    res.push(p.clone());
+                }
+            }
+        }
+        res
+    }
+
+    pub fn range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
+        let intervals = self.range_intervals(query);
+        let mut samples: Vec<Point> = Vec::new();
+        let population: usize = intervals.iter().map(|(start, end)| end - start).sum();
+        if population == 0 {
+            return samples;
+        }
+        let weights: Vec<f64> = intervals.iter().map(|(x, y)| (y - x) as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        let mut rejections = 0;
+        // For each sample, construct two level sampling.
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let offset = (weights[res] * coin3) as usize + intervals[res].0;
+            if query.contains(&self.points[offset]) {
+                samples.push(self.points[offset].clone());
+            } else {
+                rejections += 1;
+                let holds_any = || util::holds_any(query, intervals.iter().map(|(start, end)| &self.points[*start..*end]));
+                if util::gives_up(rejections, population, holds_any) { break; }
+            }
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    // Clustered points with duplicates, so that the CDF has steps and runs.
+    fn points() -> Vec<Point> {
+        let mut rng = util::seeded_rng(32);
+        (0..20000).map(|i| {
+            let (x, y): (f64, f64) = (rng.gen(), rng.gen());
+            match i % 4 {
+                0 => Point::new(100.0 * x, 100.0 * y),
+                1 => Point::new(20.0 + x, 70.0 + y * y),
+                _ => Point::new((x * 10.0).floor(), (y * 10.0).floor()),
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn predictions_stay_within_the_error_bound() {
+        let data = points();
+        for max_error in [0, 4, 32, 256].iter() {
+            let index = LearnedZVIndex::builder().max_error(*max_error).build(&data).unwrap();
+            for (i, key) in index.data.iter().enumerate() {
+                if i > 0 && index.data[i - 1] == *key {
+                    continue;
+                }
+                // The prediction is truncated to a position, so it may fall one below.
+                let guess = index.predict(*key);
+                assert!(guess + max_error + 1 >= i && guess <= i + max_error, "key at {} predicted at {}", i, guess);
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_match_binary_search() {
+        let index = LearnedZVIndex::builder().max_error(8).build(&points()).unwrap();
+        let mut rng = util::seeded_rng(33);
+        let (min, max) = (index.data[0], index.data[index.data.len() - 1]);
+        for _ in 0..20000 {
+            let key = rng.gen_range(min, max);
+            assert_eq!(index.lower_bound(key), index.data.lower_bound(&key));
+            assert_eq!(index.upper_bound(key), index.data.upper_bound(&key));
+        }
+    }
+
+    #[test]
+    fn range_matches_scan() {
+        let data = points();
+        let index = LearnedZVIndex::from(&data);
+        for query in [MBR::new(&Point::new(20.5, 70.0), &Point::new(20.7, 70.5)),
+                      MBR::new(&Point::new(2.0, 3.0), &Point::new(5.0, 3.0)),
+                      MBR::new(&Point::new(0.0, 0.0), &Point::new(50.0, 100.0))].iter() {
+            let mut found = index.range(query);
+            let mut expected: Vec<Point> = data.iter().filter(|p| query.contains(p)).cloned().collect();
+            found.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+            expected.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+            assert_eq!(found, expected);
+        }
+    }
+}
@@ -1,5 +1,101 @@
+use crate::geo::EncodeError;
+use std::fmt;
+
 pub mod kdtree;
 pub mod rstree;
 pub mod zvtree;
+pub mod hvtree;
+pub mod quadtree;
+pub mod grid;
+pub mod learned;
+pub mod rangetree;
 pub mod kdbtree;
-pub mod rsbtree;
\ No newline at end of file
+pub mod rsbtree;
+pub mod named;
+
+#[derive(PartialEq, Debug)]
+pub enum BuildError {
+    // Parameter name, its value and the smallest valid value.
+    TooSmall(&'static str, usize, usize),
+    // Name of a per-point input, its length and the number of points.
+    LengthMismatch(&'static str, usize, usize),
+    Encode(EncodeError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::TooSmall(name, value, min) => write!(f, "{} is {}, expected at least {}", name, value, min),
+            BuildError::LengthMismatch(name, len, points) => write!(f, "{} has {} values, expected one per point ({})", name, len, points),
+            BuildError::Encode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<EncodeError> for BuildError {
+    fn from(err: EncodeError) -> BuildError {
+        BuildError::Encode(err)
+    }
+}
+
+pub(crate) fn check_param(name: &'static str, value: usize, min: usize) -> Result<(), BuildError> {
+    if value < min { Err(BuildError::TooSmall(name, value, min)) } else { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::{GridEncoder, MBR, Point};
+
+    fn points() -> Vec<Point> {
+        (0..100).map(|i| Point::new((i % 10) as f64, (i / 10) as f64)).collect()
+    }
+
+    #[test]
+    fn builders_reject_small_parameters() {
+        let data = points();
+        assert_eq!(kdtree::KDTree::builder().threshold(1).build(&data).err(), Some(BuildError::TooSmall("threshold", 1, 2)));
+        assert_eq!(kdtree::KDTree::builder().threshold(1).build_with_categories(&data, &[0; 100]).err(),
+                   Some(BuildError::TooSmall("threshold", 1, 2)));
+        assert_eq!(rstree::RSTree::builder().max_entries_per_leaf(0).build(&data).err(),
+                   Some(BuildError::TooSmall("max_entries_per_leaf", 0, 1)));
+        assert_eq!(rstree::RSTree::builder().max_entries_per_node(1).build(&data).err(),
+                   Some(BuildError::TooSmall("max_entries_per_node", 1, 2)));
+        assert_eq!(zvtree::ZVTree::builder().max_entries_per_node(0).build(&data).err(),
+                   Some(BuildError::TooSmall("max_entries_per_node", 0, 1)));
+        assert_eq!(kdbtree::KDBTree::builder().buffer_size(0).build(&data).err(),
+                   Some(BuildError::TooSmall("buffer_size", 0, 1)));
+        assert_eq!(rsbtree::RSBTree::builder().buffer_size(0).build(&data).err(),
+                   Some(BuildError::TooSmall("buffer_size", 0, 1)));
+    }
+
+    #[test]
+    fn buffered_builders_check_thresholds_against_buffers() {
+        let data = points();
+        assert_eq!(kdbtree::KDBTree::builder().buffer_size(16).threshold(31).build(&data).err(),
+                   Some(BuildError::TooSmall("threshold", 31, 32)));
+        assert!(kdbtree::KDBTree::builder().buffer_size(16).threshold(32).build(&data).is_ok());
+        assert_eq!(rsbtree::RSBTree::builder().buffer_size(16).max_entries_per_leaf(31).build(&data).err(),
+                   Some(BuildError::TooSmall("max_entries_per_leaf", 31, 32)));
+        assert!(rsbtree::RSBTree::builder().buffer_size(16).max_entries_per_leaf(32).build(&data).is_ok());
+    }
+
+    #[test]
+    fn category_builds_expect_one_category_per_point() {
+        let data = points();
+        assert_eq!(kdtree::KDTree::builder().build_with_categories(&data, &[0; 99]).err(),
+                   Some(BuildError::LengthMismatch("categories", 99, 100)));
+    }
+
+    #[test]
+    fn builders_reject_points_outside_the_encoder() {
+        let data = points();
+        let encoder = GridEncoder::new(&MBR::new(&Point::new(0.0, 0.0), &Point::new(5.0, 5.0)), 0.01).unwrap();
+        match zvtree::ZVTree::builder().encoder(encoder).build(&data) {
+            Err(BuildError::Encode(EncodeError::OutOfDomain(_))) => {}
+            _ => panic!("expected an encoding error"),
+        }
+    }
+}
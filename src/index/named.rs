@@ -0,0 +1,161 @@
+use crate::geo::{MBR, Point, Region};
+use crate::util;
+use crate::index::kdtree::KDTree;
+use crate::index::kdbtree::KDBTree;
+use crate::index::rstree::RSTree;
+use crate::index::rsbtree::RSBTree;
+use crate::index::zvtree::ZVTree;
+use crate::index::hvtree::HVTree;
+use crate::index::quadtree::QuadTree;
+use crate::index::grid::GridIndex;
+use crate::index::learned::LearnedZVIndex;
+use crate::index::rangetree::RangeTree;
+
+// Index names accepted by NamedIndex::from_name. The "-olken" variants and
+// "query-then-sample" share a structure with another name but sample with a
+// different method.
+pub const INDEX_NAMES: &[&str] = &[
+    "kd", "kd-olken", "query-then-sample", "kdb", "rs", "rs-olken", "rsb",
+    "zv", "hv", "quad", "quad-olken", "grid", "learned", "range-tree",
+];
+
+// An index and sampling method picked by name, e.g. from the command line.
+pub enum NamedIndex {
+    KD(KDTree),
+    KDOlken(KDTree),
+    QueryThenSample(KDTree),
+    KDB(KDBTree),
+    RS(RSTree),
+    RSOlken(RSTree),
+    RSB(RSBTree),
+    ZV(ZVTree),
+    HV(HVTree),
+    Quad(QuadTree),
+    QuadOlken(QuadTree),
+    Grid(GridIndex),
+    Learned(LearnedZVIndex),
+    RangeTree(RangeTree),
+}
+
+impl NamedIndex {
+    // Returns None for names not in INDEX_NAMES.
+    pub fn from_name(name: &str, data: &[Point]) -> Option<NamedIndex> {
+        Some(match name {
+            "kd" => NamedIndex::KD(KDTree::from(data)),
+            "kd-olken" => NamedIndex::KDOlken(KDTree::from(data)),
+            "query-then-sample" => NamedIndex::QueryThenSample(KDTree::from(data)),
+            "kdb" => NamedIndex::KDB(KDBTree::from(data)),
+            "rs" => NamedIndex::RS(RSTree::from(data)),
+            "rs-olken" => NamedIndex::RSOlken(RSTree::from(data)),
+            "rsb" => NamedIndex::RSB(RSBTree::from(data)),
+            "zv" => NamedIndex::ZV(ZVTree::from(data)),
+            "hv" => NamedIndex::HV(HVTree::from(data)),
+            "quad" => NamedIndex::Quad(QuadTree::from(data)),
+            "quad-olken" => NamedIndex::QuadOlken(QuadTree::from(data)),
+            "grid" => NamedIndex::Grid(GridIndex::from(data)),
+            "learned" => NamedIndex::Learned(LearnedZVIndex::from(data)),
+            "range-tree" => NamedIndex::RangeTree(RangeTree::from(data)),
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NamedIndex::KD(_) => "kd",
+            NamedIndex::KDOlken(_) => "kd-olken",
+            NamedIndex::QueryThenSample(_) => "query-then-sample",
+            NamedIndex::KDB(_) => "kdb",
+            NamedIndex::RS(_) => "rs",
+            NamedIndex::RSOlken(_) => "rs-olken",
+            NamedIndex::RSB(_) => "rsb",
+            NamedIndex::ZV(_) => "zv",
+            NamedIndex::HV(_) => "hv",
+            NamedIndex::Quad(_) => "quad",
+            NamedIndex::QuadOlken(_) => "quad-olken",
+            NamedIndex::Grid(_) => "grid",
+            NamedIndex::Learned(_) => "learned",
+            NamedIndex::RangeTree(_) => "range-tree",
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            NamedIndex::KD(index) | NamedIndex::KDOlken(index) | NamedIndex::QueryThenSample(index) => index.size(),
+            NamedIndex::KDB(index) => index.size(),
+            NamedIndex::RS(index) | NamedIndex::RSOlken(index) => index.size(),
+            NamedIndex::RSB(index) => index.size(),
+            NamedIndex::ZV(index) => index.size(),
+            NamedIndex::HV(index) => index.size(),
+            NamedIndex::Quad(index) | NamedIndex::QuadOlken(index) => index.size(),
+            NamedIndex::Grid(index) => index.size(),
+            NamedIndex::Learned(index) => index.size(),
+            NamedIndex::RangeTree(index) => index.size(),
+        }
+    }
+
+    pub fn range(&self, query: &MBR) -> Vec<Point> {
+        match self {
+            NamedIndex::KD(index) | NamedIndex::KDOlken(index) | NamedIndex::QueryThenSample(index) => index.range(query),
+            NamedIndex::KDB(index) => index.range(query),
+            NamedIndex::RS(index) | NamedIndex::RSOlken(index) => index.range(query),
+            NamedIndex::RSB(index) => index.range(query),
+            NamedIndex::ZV(index) => index.range(query),
+            NamedIndex::HV(index) => index.range(query),
+            NamedIndex::Quad(index) | NamedIndex::QuadOlken(index) => index.range(query),
+            NamedIndex::Grid(index) => index.range(query),
+            NamedIndex::Learned(index) => index.range(query),
+            NamedIndex::RangeTree(index) => index.range(query),
+        }
+    }
+
+    // Indexes without aggregate counts report the size of the range result.
+    pub fn range_count(&self, query: &MBR) -> usize {
+        match self {
+            NamedIndex::KD(index) | NamedIndex::KDOlken(index) | NamedIndex::QueryThenSample(index) => index.range_count(query),
+            NamedIndex::Quad(index) | NamedIndex::QuadOlken(index) => index.range_count(query),
+            NamedIndex::Grid(index) => index.range_count(query),
+            NamedIndex::RangeTree(index) => index.range_count(query),
+            _ => self.range(query).len(),
+        }
+    }
+
+    // Buffered indexes consume their sample buffers, hence &mut self.
+    pub fn range_sampling(&mut self, query: &MBR, k: usize) -> Vec<Point> {
+        match self {
+            NamedIndex::KD(index) => index.range_sampling(query, k),
+            NamedIndex::KDOlken(index) => index.olken_range_sampling(query, k),
+            NamedIndex::QueryThenSample(index) => util::sample_from(&index.range(query), k),
+            NamedIndex::KDB(index) => index.range_sampling(query, k),
+            NamedIndex::RS(index) => index.range_sampling(query, k),
+            NamedIndex::RSOlken(index) => index.olken_range_sampling(query, k),
+            NamedIndex::RSB(index) => index.range_sampling(query, k),
+            NamedIndex::ZV(index) => index.range_sampling(query, k),
+            NamedIndex::HV(index) => index.range_sampling(query, k),
+            NamedIndex::Quad(index) => index.range_sampling(query, k),
+            NamedIndex::QuadOlken(index) => index.olken_range_sampling(query, k),
+            NamedIndex::Grid(index) => index.range_sampling(query, k),
+            NamedIndex::Learned(index) => index.range_sampling(query, k),
+            NamedIndex::RangeTree(index) => index.range_sampling(query, k),
+        }
+    }
+
+    // Points of the region, found by a range query on its bounding box.
+    pub fn region_range(&self, region: &dyn Region, bounding_box: &MBR) -> Vec<Point> {
+        self.range(bounding_box).into_iter().filter(|p| region.contains(p)).collect()
+    }
+
+    // Whether region_range_sampling is available, i.e. not only rectangles.
+    pub fn supports_regions(&self) -> bool {
+        matches!(self, NamedIndex::KD(_) | NamedIndex::RS(_) | NamedIndex::ZV(_))
+    }
+
+    // Returns None for indexes that only sample rectangles.
+    pub fn region_range_sampling(&self, region: &dyn Region, k: usize) -> Option<Vec<Point>> {
+        match self {
+            NamedIndex::KD(index) => Some(index.region_range_sampling(region, k)),
+            NamedIndex::RS(index) => Some(index.region_range_sampling(region, k)),
+            NamedIndex::ZV(index) => Some(index.region_range_sampling(region, k)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use crate::geo::{MBR, Point};
+use crate::alias::AliasTable;
+use crate::util;
+use crate::index::{BuildError, check_param};
+use std::rc::Rc;
+use rand::distributions::{Uniform, Distribution};
+
+const QUADTREE_THRESHOLD: usize = 256;
+// Bounds the depth when many points share the same location.
+const QUADTREE_MAX_DEPTH: usize = 32;
+
+struct QuadTreeNode {
+    bounding_box: MBR,
+    children: Option<Vec<Rc<QuadTreeNode>>>,
+    start: usize,
+    end: usize,
+}
+
+pub struct QuadTree {
+    root: QuadTreeNode,
+    data: Vec<Point>,
+}
+
+#[derive(Clone, Debug)]
+pub struct QuadTreeBuilder {
+    threshold: usize,
+    max_depth: usize,
+}
+
+fn partition(points: &mut [Point], f: &dyn Fn(&Point) -> bool) -> usize {
+    if points.is_empty() { 0 } else { util::partition_around(points, f) }
+}
+
+impl QuadTreeNode {
+    fn new(points: &mut [Point], depth: usize, start: usize, end: usize, bounding_box: MBR, threshold: usize, max_depth: usize) -> QuadTreeNode {
+        assert_eq!(end - start, points.len());
+        let len = points.len();
+        if len <= threshold || depth >= max_depth {
+            QuadTreeNode {
+                bounding_box,
+                children: None,
+                start,
+                end,
+            }
+        } else {
+            let center = Point::new((bounding_box.low.x + bounding_box.high.x) / 2.0,
+                                    (bounding_box.low.y + bounding_box.high.y) / 2.0);
+            let mid_x = partition(points, &|p| p.x < center.x);
+            let mid_y_left = partition(&mut points[0..mid_x], &|p| p.y < center.y);
+            let mid_y_right = mid_x + partition(&mut points[mid_x..len], &|p| p.y < center.y);
+            // Quadrants in the order of the partitioned slices: SW, NW, SE, NE.
+            let quadrants = [
+                (0, mid_y_left, MBR::new(&bounding_box.low, &center)),
+                (mid_y_left, mid_x, MBR::new(&Point::new(bounding_box.low.x, center.y), &Point::new(center.x, bounding_box.high.y))),
+                (mid_x, mid_y_right, MBR::new(&Point::new(center.x, bounding_box.low.y), &Point::new(bounding_box.high.x, center.y))),
+                (mid_y_right, len, MBR::new(&center, &bounding_box.high)),
+            ];
+            let mut children: Vec<Rc<QuadTreeNode>> = Vec::new();
+            for (lo, hi, cell) in quadrants.iter() {
+                if lo < hi {
+                    children.push(Rc::new(QuadTreeNode::new(&mut points[*lo..*hi], depth + 1, start + lo, start + hi, cell.clone(), threshold, max_depth)));
+                }
+            }
+            QuadTreeNode {
+                bounding_box,
+                children: Some(children),
+                start,
+                end,
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        48 + if let Some(children) = &self.children {
+            let mut res = 24 + children.len() * 8;
+            for child in children.iter() {
+                res += child.size();
+            }
+            res
+        } else { 0 }
+    }
+}
+
+impl QuadTreeBuilder {
+    pub fn new() -> QuadTreeBuilder {
+        QuadTreeBuilder {
+            threshold: QUADTREE_THRESHOLD,
+            max_depth: QUADTREE_MAX_DEPTH,
+        }
+    }
+
+    // Nodes with at most this many points become leaves.
+    pub fn threshold(mut self, threshold: usize) -> QuadTreeBuilder {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> QuadTreeBuilder {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn build(&self, data: &[Point]) -> Result<QuadTree, BuildError> {
+        check_param("threshold", self.threshold, 1)?;
+        Ok(QuadTree::build(data, self.threshold, self.max_depth))
+    }
+}
+
+impl Default for QuadTreeBuilder {
+    fn default() -> QuadTreeBuilder {
+        QuadTreeBuilder::new()
+    }
+}
+
+impl QuadTree {
+    pub fn from(data: &[Point]) -> QuadTree {
+        QuadTree::build(data, QUADTREE_THRESHOLD, QUADTREE_MAX_DEPTH)
+    }
+
+    pub fn builder() -> QuadTreeBuilder {
+        QuadTreeBuilder::new()
+    }
+
+    fn build(data: &[Point], threshold: usize, max_depth: usize) -> QuadTree {
+        let mut points_data: Vec<Point> = Vec::new();
+        points_data.extend_from_slice(data);
+        // Square root cell so that all quadrants are regular.
+        let mut bounding_box = MBR::from_points(data);
+        let extent = (bounding_box.high.x - bounding_box.low.x).max(bounding_box.high.y - bounding_box.low.y);
+        bounding_box.high.x = bounding_box.low.x + extent;
+        bounding_box.high.y = bounding_box.low.y + extent;
+        let root = QuadTreeNode::new(&mut points_data, 0, 0, data.len(), bounding_box, threshold, max_depth);
+        QuadTree {
+            root,
+            data: points_data,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len() * 16 + self.root.size()
+    }
+
+    pub fn range(&self, query: &MBR) -> Vec<Point> {
+        let mut res: Vec<Point> = Vec::new();
+        let mut stack: Vec<&QuadTreeNode> = Vec::new();
+        if query.intersects(&self.root.bounding_box) { stack.push(&self.root); }
+        while let Some(now) = stack.pop() {
+            match &now.children {
+                None => {
+                    for i in now.start..now.end {
+                        if query.contains(&self.data[i]) {
+                            res.push(self.data[i].clone());
+                        }
+                    }
+                }
+                Some(children) => {
+                    for child in children.iter() {
+                        if query.intersects(&child.bounding_box) { stack.push(child); }
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    pub fn range_count(&self, query: &MBR) -> usize {
+        let mut res: usize = 0;
+        let mut stack: Vec<&QuadTreeNode> = Vec::new();
+        if query.intersects(&self.root.bounding_box) { stack.push(&self.root); }
+        while let Some(now) = stack.pop() {
+            if query.contains_mbr(&now.bounding_box) {
+                res += now.end - now.start;
+            } else {
+                match &now.children {
+                    None => {
+                        res += self.data[now.start..now.end].iter().filter(|p| query.contains(p)).count();
+                    }
+                    Some(children) => {
+                        for child in children.iter() {
+                            if query.intersects(&child.bounding_box) { stack.push(child); }
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    pub fn olken_range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        if self.data.is_empty() || !query.intersects(&self.root.bounding_box) {
+            return samples;
+        }
+
+        let mut rng = util::new_rng();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rejections = 0;
+        while samples.len() < k {
+            let accepted = samples.len();
+            let mut now: &QuadTreeNode = &self.root;
+            loop {
+                match &now.children {
+                    None => {
+                        let offset = (dist.sample(&mut rng) * (now.end - now.start) as f64) as usize + now.start;
+                        if query.contains(&self.data[offset]) {
+                            samples.push(self.data[offset].clone());
+                        }
+                        break;
+                    }
+                    Some(children) => {
+                        // Children split the run of their parent in order, so
+                        // a uniform offset in it picks one by its count.
+                        let offset = (dist.sample(&mut rng) * (now.end - now.start) as f64) as usize + now.start;
+                        now = children.iter().find(|child| offset < child.end).unwrap().as_ref();
+                        //Rejection
+                        if !query.intersects(&now.bounding_box) {
+                            break;
+                        }
+                    }
+                }
+            }
+            if samples.len() == accepted {
+                rejections += 1;
+                if util::gives_up(rejections, self.data.len(), || self.range_count(query) > 0) { break; }
+            }
+        }
+        samples
+    }
+
+    pub fn range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        let mut candidates: Vec<&QuadTreeNode> = Vec::new();
+        let mut stack: Vec<&QuadTreeNode> = Vec::new();
+        if query.intersects(&self.root.bounding_box) { stack.push(&self.root); }
+        while let Some(now) = stack.pop() {
+            if query.contains_mbr(&now.bounding_box) {
+                candidates.push(now);
+            } else {
+                match &now.children {
+                    None => {
+                        candidates.push(now);
+                    }
+                    Some(children) => {
+                        for child in children.iter() {
+                            if query.intersects(&child.bounding_box) { stack.push(child); }
+                        }
+                    }
+                }
+            }
+        }
+        let population: usize = candidates.iter().map(|node| node.end - node.start).sum();
+        if population == 0 {
+            return samples;
+        }
+        // Construct top level alias structure
+        let weights: Vec<f64> = candidates.iter().map(|node| (node.end - node.start) as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        let mut rejections = 0;
+
+        // For each sample, construct two level sampling.
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let offset = (weights[res] * coin3) as usize + candidates[res].start;
+            if query.contains(&self.data[offset]) {
+                samples.push(self.data[offset].clone());
+            } else {
+                rejections += 1;
+                if util::gives_up(rejections, population, || self.range_count(query) > 0) { break; }
+            }
+        }
+
+        samples
+    }
+}
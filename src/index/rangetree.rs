@@ -0,0 +1,167 @@
+use crate::geo::{MBR, Point};
+use crate::alias::AliasTable;
+use crate::util;
+use rand::distributions::{Uniform, Distribution};
+
+// A 2D range tree laid out as a merge sort tree: points are sorted by x, and
+// level l stores the aligned blocks of 2^l consecutive points each sorted by y.
+// A query splits its x-range into O(log n) canonical blocks, in each of which
+// the points inside the y-range form one contiguous interval, so sampling
+// needs no rejection.
+pub struct RangeTree {
+    levels: Vec<Vec<Point>>,
+}
+
+fn merge_by_y(left: &[Point], right: &[Point], res: &mut Vec<Point>) {
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if right[j].y < left[i].y {
+            res.push(right[j].clone());
+            j += 1;
+        } else {
+            res.push(left[i].clone());
+            i += 1;
+        }
+    }
+    res.extend_from_slice(&left[i..]);
+    res.extend_from_slice(&right[j..]);
+}
+
+impl RangeTree {
+    pub fn from(data: &[Point]) -> RangeTree {
+        let mut points: Vec<Point> = Vec::new();
+        points.extend_from_slice(data);
+        points.sort_unstable_by(|p1, p2| p1.x.partial_cmp(&p2.x).unwrap());
+        let len = points.len();
+        let mut levels: Vec<Vec<Point>> = vec![points];
+        let mut block: usize = 1;
+        while block < len {
+            let prev = levels.last().unwrap();
+            let mut level: Vec<Point> = Vec::with_capacity(len);
+            let mut start: usize = 0;
+            while start < len {
+                let mid = (start + block).min(len);
+                let end = (start + 2 * block).min(len);
+                merge_by_y(&prev[start..mid], &prev[mid..end], &mut level);
+                start = end;
+            }
+            levels.push(level);
+            block *= 2;
+        }
+        RangeTree {
+            levels,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.levels.iter().map(|level| 24 + level.len() * 16).sum()
+    }
+
+    // Intervals (level, start, end) of points inside the query.
+    fn range_intervals(&self, query: &MBR) -> Vec<(usize, usize, usize)> {
+        let mut intervals: Vec<(usize, usize, usize)> = Vec::new();
+        let xs = &self.levels[0];
+        let len = xs.len();
+        let mut low = xs.partition_point(|p| p.x < query.low.x);
+        let high = xs.partition_point(|p| p.x <= query.high.x);
+        while low < high {
+            // Largest aligned block starting at low and ending within the x-range.
+            let mut level = if low == 0 { self.levels.len() - 1 } else { (low.trailing_zeros() as usize).min(self.levels.len() - 1) };
+            while (low + (1 << level)).min(len) > high {
+                level -= 1;
+            }
+            let end = (low + (1 << level)).min(len);
+            let block = &self.levels[level][low..end];
+            let start_y = block.partition_point(|p| p.y < query.low.y);
+            let end_y = block.partition_point(|p| p.y <= query.high.y);
+            if start_y < end_y {
+                intervals.push((level, low + start_y, low + end_y));
+            }
+            low = end;
+        }
+        intervals
+    }
+
+    pub fn range(&self, query: &MBR) -> Vec<Point> {
+        let mut res: Vec<Point> = Vec::new();
+        for (level, start, end) in self.range_intervals(query).iter() {
+            res.extend_from_slice(&self.levels[*level][*start..*end]);
+        }
+        res
+    }
+
+    pub fn range_count(&self, query: &MBR) -> usize {
+        self.range_intervals(query).iter().map(|(_, start, end)| end - start).sum()
+    }
+
+    pub fn range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        let intervals = self.range_intervals(query);
+        if intervals.is_empty() {
+            return samples;
+        }
+        let weights: Vec<f64> = intervals.iter().map(|(_, start, end)| (end - start) as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+
+        // Every point of every interval lies in the query, so no rejection.
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let (level, start, _) = intervals[res];
+            samples.push(self.levels[level][start + (weights[res] * coin3) as usize].clone());
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut points: Vec<Point>) -> Vec<Point> {
+        points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap().then(a.y.partial_cmp(&b.y).unwrap()));
+        points
+    }
+
+    // A 37 x 37 lattice with every point twice, so that blocks are not powers
+    // of two and queries have points on their borders.
+    fn lattice() -> Vec<Point> {
+        (0..2 * 37 * 37).map(|i| Point::new((i % 37) as f64, (i / 2 % 37) as f64)).collect()
+    }
+
+    #[test]
+    fn range_matches_scan() {
+        let data = lattice();
+        let tree = RangeTree::from(&data);
+        for (x1, y1, x2, y2) in [(3.0, 4.0, 20.0, 9.0), (0.0, 0.0, 36.0, 36.0), (5.5, 5.5, 5.9, 30.0), (7.0, 7.0, 7.0, 7.0), (40.0, 0.0, 50.0, 10.0)].iter() {
+            let query = MBR::new(&Point::new(*x1, *y1), &Point::new(*x2, *y2));
+            let exact: Vec<Point> = data.iter().filter(|p| query.contains(p)).cloned().collect();
+            assert_eq!(tree.range_count(&query), exact.len());
+            assert_eq!(sorted(tree.range(&query)), sorted(exact));
+        }
+    }
+
+    #[test]
+    fn samples_need_no_rejection() {
+        let tree = RangeTree::from(&lattice());
+        let query = MBR::new(&Point::new(3.0, 4.0), &Point::new(20.0, 9.0));
+        let samples = tree.range_sampling(&query, 1000);
+        assert_eq!(samples.len(), 1000);
+        assert!(samples.iter().all(|p| query.contains(p)));
+        let empty = MBR::new(&Point::new(5.5, 5.5), &Point::new(5.9, 30.0));
+        assert!(tree.range_sampling(&empty, 10).is_empty());
+    }
+
+    #[test]
+    fn empty_and_single_point() {
+        let query = MBR::new(&Point::new(0.0, 0.0), &Point::new(10.0, 10.0));
+        assert!(RangeTree::from(&[]).range_sampling(&query, 10).is_empty());
+        let tree = RangeTree::from(&[Point::new(1.0, 2.0)]);
+        assert_eq!(tree.range(&query), vec![Point::new(1.0, 2.0)]);
+        assert_eq!(tree.range_sampling(&query, 3), vec![Point::new(1.0, 2.0); 3]);
+    }
+}
@@ -2,6 +2,7 @@
 use crate::geo::{MBR, Point};
 use crate::alias::AliasTable;
 use crate::util;
+use crate::index::{BuildError, check_param};
 use std::rc::Rc;
 use rand::distributions::{Uniform, Distribution};
 
@@ -99,6 +100,55 @@ impl RSBTreeNode {
 pub struct RSBTree {
     root: RSBTreeNode,
     data: Vec<Point>,
+    buffer_size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct RSBTreeBuilder {
+    max_entries_per_leaf: usize,
+    max_entries_per_node: usize,
+    buffer_size: usize,
+}
+
+impl RSBTreeBuilder {
+    pub fn new() -> RSBTreeBuilder {
+        RSBTreeBuilder {
+            max_entries_per_leaf: MAX_ENTRIES_PER_LEAF,
+            max_entries_per_node: MAX_ENTRIES_PER_NODE,
+            buffer_size: RSB_SAMPLE_BUFFER_SIZE,
+        }
+    }
+
+    pub fn max_entries_per_leaf(mut self, max_entries_per_leaf: usize) -> RSBTreeBuilder {
+        self.max_entries_per_leaf = max_entries_per_leaf;
+        self
+    }
+
+    pub fn max_entries_per_node(mut self, max_entries_per_node: usize) -> RSBTreeBuilder {
+        self.max_entries_per_node = max_entries_per_node;
+        self
+    }
+
+    // Samples buffered at every internal node.
+    pub fn buffer_size(mut self, buffer_size: usize) -> RSBTreeBuilder {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    // As in KDBTree, a leaf must hold at least twice as many points as a
+    // buffer, so that every internal node holds more points than its buffer.
+    pub fn build(&self, data: &[Point]) -> Result<RSBTree, BuildError> {
+        check_param("max_entries_per_node", self.max_entries_per_node, 2)?;
+        check_param("buffer_size", self.buffer_size, 1)?;
+        check_param("max_entries_per_leaf", self.max_entries_per_leaf, 2 * self.buffer_size)?;
+        Ok(RSBTree::build(data, self.max_entries_per_leaf, self.max_entries_per_node, self.buffer_size))
+    }
+}
+
+impl Default for RSBTreeBuilder {
+    fn default() -> RSBTreeBuilder {
+        RSBTreeBuilder::new()
+    }
 }
 
 impl RSBTree {
@@ -107,9 +157,17 @@ impl RSBTree {
     }
 
     pub fn from(data: &[Point]) -> RSBTree {
+        RSBTree::build(data, MAX_ENTRIES_PER_LEAF, MAX_ENTRIES_PER_NODE, RSB_SAMPLE_BUFFER_SIZE)
+    }
+
+    pub fn builder() -> RSBTreeBuilder {
+        RSBTreeBuilder::new()
+    }
+
+    fn build(data: &[Point], max_entries_per_leaf: usize, max_entries_per_node: usize, buffer_size: usize) -> RSBTree {
         let mut points: Vec<Point> = Vec::new();
         points.extend_from_slice(data);
-        let mut now = (points.len() as f64 / MAX_ENTRIES_PER_LEAF as f64).ceil() as usize;
+        let mut now = (points.len() as f64 / max_entries_per_leaf as f64).ceil() as usize;
         let length = points.len();
         let now_x = (now as f64).sqrt().ceil() as usize;
         let now_y = (now as f64 / now_x as f64).ceil() as usize;
@@ -130,7 +188,7 @@ impl RSBTree {
             i += slice_x.len();
         }
 
-        now = (rtree_nodes.len() as f64 / MAX_ENTRIES_PER_NODE as f64).ceil() as usize;
+        now = (rtree_nodes.len() as f64 / max_entries_per_node as f64).ceil() as usize;
         while now > 1 {
             let now_x = (now as f64).ceil().sqrt() as usize;
             let now_y = (now as f64 / now_x as f64).ceil() as usize;
@@ -156,7 +214,7 @@ impl RSBTree {
                 i += slice_x.len();
             }
             rtree_nodes = tmp_nodes;
-            now = (rtree_nodes.len() as f64 / MAX_ENTRIES_PER_NODE as f64) as usize;
+            now = (rtree_nodes.len() as f64 / max_entries_per_node as f64) as usize;
         }
 
         //Layingout data
@@ -197,13 +255,14 @@ impl RSBTree {
                     offset -= child.size;
                     stack.push((&**child as *const RSBTreeNode as *mut RSBTreeNode, offset));
                 }
-                node.sample_buffer = util::sample_from(&layout[node.offset..(node.offset + node.size)], RSB_SAMPLE_BUFFER_SIZE);
+                node.sample_buffer = util::sample_from(&layout[node.offset..(node.offset + node.size)], buffer_size);
             }
         }
 
         RSBTree {
             root,
             data: layout,
+            buffer_size,
         }
     }
 
@@ -238,11 +297,18 @@ impl RSBTree {
         let mut frontier: Vec<*mut RSBTreeNode> = Vec::new();
         let mut new_frontier: Vec<*mut RSBTreeNode> = Vec::new();
         let mut weights: Vec<f64> = Vec::new();
-        frontier.push(&mut self.root as *mut RSBTreeNode);
+        if self.root.size > 0 && self.root.bounding_box.intersects(query) {
+            frontier.push(&mut self.root as *mut RSBTreeNode);
+        }
+        if frontier.is_empty() {
+            return samples;
+        }
         let mut alias = AliasTable::uniform(1);
         let mut rng = util::new_rng();
         let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rejections = 0;
         while samples.len() < k {
+            let accepted = samples.len();
             let coin1 = dist.sample(&mut rng);
             let coin2 = dist.sample(&mut rng);
             let offset = alias.sample(coin1, coin2);
@@ -279,16 +345,56 @@ impl RSBTree {
                             }
                         }
                         //replenish buffer
-                        node.sample_buffer = util::sample_from(&self.data[node.offset..(node.offset + node.size)], RSB_SAMPLE_BUFFER_SIZE);
+                        node.sample_buffer = util::sample_from(&self.data[node.offset..(node.offset + node.size)], self.buffer_size);
                         node.valid_ptr = 0;
                     }
                 }
             }
             if flag {
                 std::mem::swap(&mut frontier, &mut new_frontier);
+                if frontier.is_empty() {
+                    return samples;
+                }
                 alias = AliasTable::from(&weights);
             }
+            if samples.len() == accepted {
+                rejections += 1;
+                // The frontier always covers every point of the query.
+                let holds_any = || util::holds_any(query, frontier.iter().map(|node| unsafe { &self.data[(**node).offset..((**node).offset + (**node).size)] }));
+                if util::gives_up(rejections, self.root.size, holds_any) { break; }
+            }
         }
         samples
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lattice(side: usize) -> Vec<Point> {
+        (0..side * side).map(|i| Point::new((i % side) as f64, (i / side) as f64)).collect()
+    }
+
+    #[test]
+    fn no_samples_without_points() {
+        let mut tree = RSBTree::builder().buffer_size(16).max_entries_per_leaf(32).build(&lattice(40)).unwrap();
+        // Between lattice points, across many leaves, and outside of the data.
+        assert!(tree.range_sampling(&MBR::new(&Point::new(3.2, 0.0), &Point::new(3.8, 39.0)), 10).is_empty());
+        assert!(tree.range_sampling(&MBR::new(&Point::new(50.0, 50.0), &Point::new(60.0, 60.0)), 10).is_empty());
+        assert!(RSBTree::from(&[]).range_sampling(&MBR::new(&Point::new(0.0, 0.0), &Point::new(1.0, 1.0)), 10).is_empty());
+    }
+
+    #[test]
+    fn samples_after_buffers_run_out() {
+        let mut tree = RSBTree::builder().buffer_size(16).max_entries_per_leaf(32).build(&lattice(40)).unwrap();
+        let query = MBR::new(&Point::new(2.5, 2.5), &Point::new(30.5, 12.5));
+        for _ in 0..3 {
+            let samples = tree.range_sampling(&query, 1000);
+            assert_eq!(samples.len(), 1000);
+            assert!(samples.iter().all(|p| query.contains(p)));
+        }
+        let single = MBR::new(&Point::new(7.0, 7.0), &Point::new(7.0, 7.0));
+        assert_eq!(tree.range_sampling(&single, 50), vec![Point::new(7.0, 7.0); 50]);
+    }
+}
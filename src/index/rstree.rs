@@ -1,6 +1,7 @@
-use crate::geo::{MBR, Point};
+use crate::geo::{MBR, Point, Region};
 use crate::alias::AliasTable;
 use crate::util;
+use crate::index::{BuildError, check_param};
 use std::rc::Rc;
 use rand::distributions::{Uniform, Distribution};
 
@@ -90,18 +91,63 @@ pub struct RSTree {
     data: Vec<Point>,
 }
 
+#[derive(Clone, Debug)]
+pub struct RSTreeBuilder {
+    max_entries_per_leaf: usize,
+    max_entries_per_node: usize,
+}
+
 const MAX_ENTRIES_PER_LEAF: usize = 256;
 const MAX_ENTRIES_PER_NODE: usize = 25;
 
+impl RSTreeBuilder {
+    pub fn new() -> RSTreeBuilder {
+        RSTreeBuilder {
+            max_entries_per_leaf: MAX_ENTRIES_PER_LEAF,
+            max_entries_per_node: MAX_ENTRIES_PER_NODE,
+        }
+    }
+
+    pub fn max_entries_per_leaf(mut self, max_entries_per_leaf: usize) -> RSTreeBuilder {
+        self.max_entries_per_leaf = max_entries_per_leaf;
+        self
+    }
+
+    pub fn max_entries_per_node(mut self, max_entries_per_node: usize) -> RSTreeBuilder {
+        self.max_entries_per_node = max_entries_per_node;
+        self
+    }
+
+    pub fn build(&self, data: &[Point]) -> Result<RSTree, BuildError> {
+        check_param("max_entries_per_leaf", self.max_entries_per_leaf, 1)?;
+        check_param("max_entries_per_node", self.max_entries_per_node, 2)?;
+        Ok(RSTree::build(data, self.max_entries_per_leaf, self.max_entries_per_node))
+    }
+}
+
+impl Default for RSTreeBuilder {
+    fn default() -> RSTreeBuilder {
+        RSTreeBuilder::new()
+    }
+}
+
 impl RSTree {
     pub fn size(&self) -> usize {
         self.root.size() + self.data.len() * 16
     }
 
     pub fn from(data: &[Point]) -> RSTree {
+        RSTree::build(data, MAX_ENTRIES_PER_LEAF, MAX_ENTRIES_PER_NODE)
+    }
+
+    pub fn builder() -> RSTreeBuilder {
+        RSTreeBuilder::new()
+    }
+
+    fn build(data: &[Point], max_entries_per_leaf: usize, max_entries_per_node: usize) -> RSTree {
         let mut points: Vec<Point> = Vec::new();
         points.extend_from_slice(data);
-        let mut now = (points.len() as f64 / MAX_ENTRIES_PER_LEAF as f64).ceil() as usize;
+        let mut now = (points.len() as f64 / max_entries_per_leaf as f64).ceil() as usize;
         let length = points.len();
         let now_x = (now as f64).sqrt().ceil() as usize;
         let now_y = (now as f64 / now_x as f64).ceil() as usize;
@@ -122,7 +168,7 @@ impl RSTree {
             i += slice_x.len();
         }
 
-        now = (rtree_nodes.len() as f64 / MAX_ENTRIES_PER_NODE as f64).ceil() as usize;
+        now = (rtree_nodes.len() as f64 / max_entries_per_node as f64).ceil() as usize;
         while now > 1 {
             let now_x = (now as f64).ceil().sqrt() as usize;
             let now_y = (now as f64 / now_x as f64).ceil() as usize;
@@ -148,7 +194,7 @@ impl RSTree {
                 i += slice_x.len();
             }
             rtree_nodes = tmp_nodes;
-            now = (rtree_nodes.len() as f64 / MAX_ENTRIES_PER_NODE as f64) as usize;
+            now = (rtree_nodes.len() as f64 / max_entries_per_node as f64) as usize;
         }
 
         let root = RSTreeNode::from_nodes(rtree_nodes.as_slice());
@@ -210,7 +256,7 @@ impl RSTree {
 
     pub fn olken_range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
         let mut samples: Vec<Point> = Vec::new();
-        if !query.intersects(&self.root.bounding_box) {
+        if self.data.is_empty() || !query.intersects(&self.root.bounding_box) {
             return samples;
         }
         let mut lca_root: &RSTreeNode = &self.root;
@@ -234,7 +280,9 @@ impl RSTree {
 
         let mut rng = util::new_rng();
         let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rejections = 0;
         while samples.len() < k {
+            let accepted = samples.len();
             let mut now: &RSTreeNode = &self.root;
             loop {
                 match &now.children {
@@ -258,6 +306,10 @@ impl RSTree {
                     }
                 }
             }
+            if samples.len() == accepted {
+                rejections += 1;
+                if util::gives_up(rejections, self.root.size, || self.holds_any(query)) { break; }
+            }
         }
         samples
     }
@@ -291,13 +343,17 @@ impl RSTree {
             }
         }
         //let spare_alias = AliasTable::uniform(spare.len());
-        
+        let population: usize = candidates.iter().map(|node| node.size).sum();
+        if population == 0 {
+            return samples;
+        }
         // Construct top level alias structure
         //let mut weights = vec![spare.len() as f64];
         let weights: Vec<f64> = candidates.iter().map(|node| node.size as f64).collect();
         let dist = Uniform::from(0.0f64..1.0f64);
         let mut rng = util::new_rng();
         let top_level_alias = AliasTable::from(&weights);
+        let mut rejections = 0;
 
         // For each sample, construct two level sampling.
         while samples.len() < k {
@@ -308,10 +364,120 @@ impl RSTree {
             let offset = (weights[res] * coin3) as usize  + candidates[res].offset;
             if query.contains(&self.data[offset]) {
                 samples.push(self.data[offset].clone());
+            } else {
+                rejections += 1;
+                if util::gives_up(rejections, population, || self.holds_any(query)) { break; }
             }
         }
 
         samples
     }
+
+    // Two level sampling over any region, e.g. a union of rectangles: every
+    // node is a candidate at most once and a point is accepted if the region
+    // contains it, so points in overlaps are not over-represented.
+    pub fn region_range_sampling(&self, region: &dyn Region, k: usize) -> Vec<Point> {
+        let mut samples: Vec<Point> = Vec::new();
+        let candidates = self.candidates(region);
+        let population: usize = candidates.iter().map(|node| node.size).sum();
+        if population == 0 {
+            return samples;
+        }
+
+        let weights: Vec<f64> = candidates.iter().map(|node| node.size as f64).collect();
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let mut rng = util::new_rng();
+        let top_level_alias = AliasTable::from(&weights);
+        let mut rejections = 0;
+        while samples.len() < k {
+            let coin1 = dist.sample(&mut rng);
+            let coin2 = dist.sample(&mut rng);
+            let res = top_level_alias.sample(coin1, coin2);
+            let coin3 = dist.sample(&mut rng);
+            let offset = (weights[res] * coin3) as usize + candidates[res].offset;
+            if region.contains(&self.data[offset]) {
+                samples.push(self.data[offset].clone());
+            } else {
+                rejections += 1;
+                if util::gives_up(rejections, population, || self.holds_any(region)) { break; }
+            }
+        }
+        samples
+    }
+
+    // Whether the region holds a point, for samplers to stop on ranges
+    // without any.
+    fn holds_any(&self, region: &dyn Region) -> bool {
+        util::holds_any(region, self.candidates(region).iter().map(|node| &self.data[node.offset..(node.offset + node.size)]))
+    }
+
+    fn candidates<'a>(&'a self, region: &dyn Region) -> Vec<&'a RSTreeNode> {
+        let mut candidates: Vec<&RSTreeNode> = Vec::new();
+        let mut stack: Vec<&RSTreeNode> = Vec::new();
+        if region.intersects(&self.root.bounding_box) { stack.push(&self.root); }
+        while let Some(now) = stack.pop() {
+            if region.contains_mbr(&now.bounding_box) {
+                candidates.push(now);
+            } else {
+                match &now.children {
+                    Some(children) => {
+                        for child in children.iter() {
+                            if region.intersects(&child.bounding_box) {
+                                stack.push(child);
+                            }
+                        }
+                    }
+                    None => {
+                        candidates.push(now);
+                    }
+                }
+            }
+        }
+        candidates
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::{MBRDifference, MultiMBR};
+
+    fn lattice(side: usize) -> Vec<Point> {
+        (0..side * side).map(|i| Point::new((i % side) as f64, (i / side) as f64)).collect()
+    }
+
+    #[test]
+    fn union_samples_do_not_favour_overlaps() {
+        let tree = RSTree::from(&lattice(40));
+        // 700 points in the union, 100 of them in both rectangles.
+        let union = MultiMBR::new(&[MBR::new(&Point::new(0.5, 0.5), &Point::new(20.5, 20.5)),
+                                    MBR::new(&Point::new(10.5, 10.5), &Point::new(30.5, 30.5))]);
+        let samples = tree.region_range_sampling(&union, 20000);
+        assert_eq!(samples.len(), 20000);
+        assert!(samples.iter().all(|p| union.contains(p)));
+        let overlap = samples.iter().filter(|p| p.x > 10.0 && p.x < 21.0 && p.y > 10.0 && p.y < 21.0).count();
+        assert!((overlap as f64 / 20000.0 - 1.0 / 7.0).abs() < 0.015, "{} samples in the overlap", overlap);
+    }
+
+    #[test]
+    fn union_without_points() {
+        let tree = RSTree::from(&lattice(40));
+        let empty = MultiMBR::new(&[MBR::new(&Point::new(3.2, 0.0), &Point::new(3.8, 39.0)),
+                                    MBR::new(&Point::new(0.0, 7.1), &Point::new(39.0, 7.9))]);
+        assert!(tree.region_range_sampling(&empty, 10).is_empty());
+        assert!(RSTree::from(&[]).region_range_sampling(&empty, 10).is_empty());
+    }
+
+    #[test]
+    fn difference_samples_avoid_holes() {
+        let tree = RSTree::from(&lattice(40));
+        let holes = MBRDifference::new(&MBR::new(&Point::new(4.5, 4.5), &Point::new(30.5, 30.5)),
+                                       &[MBR::new(&Point::new(5.5, 5.5), &Point::new(20.5, 20.5))]);
+        let samples = tree.region_range_sampling(&holes, 2000);
+        assert_eq!(samples.len(), 2000);
+        assert!(samples.iter().all(|p| holes.contains(p)));
+        let covered = MBRDifference::new(&MBR::new(&Point::new(4.5, 4.5), &Point::new(30.5, 30.5)),
+                                         &[MBR::new(&Point::new(4.0, 4.0), &Point::new(31.0, 31.0))]);
+        assert!(tree.region_range_sampling(&covered, 10).is_empty());
+    }
+}
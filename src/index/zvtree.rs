@@ -1,11 +1,85 @@
 use crate::alias::AliasTable;
-use crate::geo::{MBR, Point};
+use crate::geo::{MBR, Point, Region, GridEncoder, EncodeError};
 use crate::util;
+use crate::index::{BuildError, check_param};
 use superslice::*;
 use std::rc::Rc;
+use std::collections::BinaryHeap;
 use rand::distributions::{Uniform, Distribution};
 
 const MAX_ENTRIES_PER_NODE: usize = 256;
+// Interval budget of bigmin_range_sampling when callers have no better one;
+// the exact decomposition of a large box can have very many intervals.
+pub const DEFAULT_BIGMIN_MAX_INTERVALS: usize = 256;
+
+// Inclusive grid cells (lowx, lowy, highx, highy).
+type GridBox = (u32, u32, u32, u32);
+
+// Splits a grid box at the most significant bit where the z-values of its
+// corners differ. The z-value of the first half's high corner is LITMAX and
+// that of the second half's low corner is BIGMIN.
+fn split_zbox(bound: GridBox) -> (GridBox, GridBox) {
+    let (lowx, lowy, highx, highy) = bound;
+    let diff = Point::compose_zvalue(lowx, lowy) ^ Point::compose_zvalue(highx, highy);
+    let msb = 63 - diff.leading_zeros();
+    let bit = msb / 2;
+    if msb % 2 == 1 {
+        let mid = highx >> bit << bit;
+        ((lowx, lowy, mid - 1, highy), (mid, lowy, highx, highy))
+    } else {
+        let mid = highy >> bit << bit;
+        ((lowx, lowy, highx, mid - 1), (lowx, mid, highx, highy))
+    }
+}
+
+// Decomposes a grid box into intervals of positions in sorted z-value data.
+// `search` maps a z-value range to the (lower_bound, upper_bound) positions of
+// the data. Sub-boxes without data are skipped, boxes whose z-range matches
+// their cells exactly are emitted as is, and the others are split, the most
+// populated first, until the budget of `max_intervals` is used up.
+pub(crate) fn zrange_intervals(bound: GridBox, max_intervals: usize, search: &dyn Fn(u64, u64) -> (usize, usize)) -> Vec<(usize, usize)> {
+    let mut intervals: Vec<(usize, usize)> = Vec::new();
+    let mut heap: BinaryHeap<(usize, usize, usize, GridBox)> = BinaryHeap::new();
+    let visit = |bound: GridBox, intervals: &mut Vec<(usize, usize)>, heap: &mut BinaryHeap<_>| {
+        let (lowx, lowy, highx, highy) = bound;
+        let zmin = Point::compose_zvalue(lowx, lowy);
+        let zmax = Point::compose_zvalue(highx, highy);
+        let (start, end) = search(zmin, zmax);
+        if start < end {
+            let cells = (highx - lowx) as u128 + 1;
+            let cells = cells * ((highy - lowy) as u128 + 1);
+            if cells == (zmax - zmin) as u128 + 1 {
+                intervals.push((start, end));
+            } else {
+                heap.push((end - start, start, end, bound));
+            }
+        }
+    };
+    visit(bound, &mut intervals, &mut heap);
+    while intervals.len() + heap.len() < max_intervals {
+        match heap.pop() {
+            None => { break; }
+            Some((_, _, _, bound)) => {
+                let (first, second) = split_zbox(bound);
+                visit(first, &mut intervals, &mut heap);
+                visit(second, &mut intervals, &mut heap);
+            }
+        }
+    }
+    for (_, start, end, _) in heap.into_iter() {
+        intervals.push((start, end));
+    }
+
+    intervals.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in intervals.into_iter() {
+        match merged.last_mut() {
+            Some(last) if last.1 == start => { last.1 = end; }
+            _ => { merged.push((start, end)); }
+        }
+    }
+    merged
+}
 
 struct ZVTreeNode {
     children: Option<(Rc<ZVTreeNode>, Rc<ZVTreeNode>, Rc<ZVTreeNode>, Rc<ZVTreeNode>)>,
@@ -14,8 +88,8 @@ struct ZVTreeNode {
 }
 
 impl ZVTreeNode {
-    fn from(level: u8, high_bits: u64, data: &[u64], start: usize, end: usize) -> ZVTreeNode {
-        if level == 32 || end - start <= MAX_ENTRIES_PER_NODE {
+    fn from(level: u8, high_bits: u64, data: &[u64], start: usize, end: usize, max_entries: usize) -> ZVTreeNode {
+        if level == 32 || end - start <= max_entries {
             ZVTreeNode {
                 children: None,
                 start,
@@ -30,10 +104,10 @@ impl ZVTreeNode {
             let bound4 = high_bits + (0b11_u64 << shift);
             let bound5 = high_bits + (0b11_u64 << shift) + low_bits;
             let data_slice = &data[start..end];
-            let node1 = Rc::new(ZVTreeNode::from(level + 1, bound1, data, start + data_slice.lower_bound(&bound1), start + data_slice.upper_bound(&(bound2 - 1))));
-            let node2 = Rc::new(ZVTreeNode::from(level + 1, bound2, data, start + data_slice.lower_bound(&bound2), start + data_slice.upper_bound(&(bound3 - 1))));
-            let node3 = Rc::new(ZVTreeNode::from(level + 1, bound3, data, start + data_slice.lower_bound(&bound3), start + data_slice.upper_bound(&(bound4 - 1))));
-            let node4 = Rc::new(ZVTreeNode::from(level + 1, bound4, data, start + data_slice.lower_bound(&bound4), start + data_slice.upper_bound(&bound5)));
+            let node1 = Rc::new(ZVTreeNode::from(level + 1, bound1, data, start + data_slice.lower_bound(&bound1), start + data_slice.upper_bound(&(bound2 - 1)), max_entries));
+            let node2 = Rc::new(ZVTreeNode::from(level + 1, bound2, data, start + data_slice.lower_bound(&bound2), start + data_slice.upper_bound(&(bound3 - 1)), max_entries));
+            let node3 = Rc::new(ZVTreeNode::from(level + 1, bound3, data, start + data_slice.lower_bound(&bound3), start + data_slice.upper_bound(&(bound4 - 1)), max_entries));
+            let node4 = Rc::new(ZVTreeNode::from(level + 1, bound4, data, start + data_slice.lower_bound(&bound4), start + data_slice.upper_bound(&bound5), max_entries));
             ZVTreeNode {
                 children: Some((node1, node2, node3, node4)),
                 start,
@@ -52,79 +126,140 @@ impl ZVTreeNode {
 pub struct ZVTree {
     root: ZVTreeNode,
     data: Vec<u64>,
+    // Original coordinates, permuted alongside the sorted z-values.
+    points: Vec<Point>,
+    encoder: GridEncoder,
+}
+
+#[derive(Clone, Debug)]
+pub struct ZVTreeBuilder {
+    max_entries_per_node: usize,
+    encoder: Option<GridEncoder>,
+}
+
+impl ZVTreeBuilder {
+    pub fn new() -> ZVTreeBuilder {
+        ZVTreeBuilder {
+            max_entries_per_node: MAX_ENTRIES_PER_NODE,
+            encoder: None,
+        }
+    }
+
+    pub fn max_entries_per_node(mut self, max_entries_per_node: usize) -> ZVTreeBuilder {
+        self.max_entries_per_node = max_entries_per_node;
+        self
+    }
+
+    // Defaults to an encoder fitted to the input.
+    pub fn encoder(mut self, encoder: GridEncoder) -> ZVTreeBuilder {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    pub fn build(&self, input: &[Point]) -> Result<ZVTree, BuildError> {
+        check_param("max_entries_per_node", self.max_entries_per_node, 1)?;
+        let encoder = self.encoder.clone().unwrap_or_else(|| GridEncoder::fit(input));
+        ZVTree::build(input, encoder, self.max_entries_per_node).map_err(BuildError::from)
+    }
+}
+
+impl Default for ZVTreeBuilder {
+    fn default() -> ZVTreeBuilder {
+        ZVTreeBuilder::new()
+    }
 }
 
 impl ZVTree {
     pub fn from(input: &[Point]) -> ZVTree {
-        let mut data: Vec<u64> = input.iter().map(|p| p.to_zvalue()).collect();
-        data.sort_unstable();
-        ZVTree {
-            root: ZVTreeNode::from(0, 0, &data, 0, data.len()),
-            data,
+        ZVTree::with_encoder(input, GridEncoder::fit(input)).expect("Expect finite coordinates")
+    }
+
+    pub fn builder() -> ZVTreeBuilder {
+        ZVTreeBuilder::new()
+    }
+
+    pub fn with_encoder(input: &[Point], encoder: GridEncoder) -> Result<ZVTree, EncodeError> {
+        ZVTree::build(input, encoder, MAX_ENTRIES_PER_NODE)
+    }
+
+    fn build(input: &[Point], encoder: GridEncoder, max_entries: usize) -> Result<ZVTree, EncodeError> {
+        let mut entries: Vec<(u64, Point)> = Vec::new();
+        for p in input.iter() {
+            entries.push((encoder.to_zvalue(p)?, p.clone()));
         }
+        entries.sort_unstable_by_key(|e| e.0);
+        let (data, points): (Vec<u64>, Vec<Point>) = entries.into_iter().unzip();
+        Ok(ZVTree {
+            root: ZVTreeNode::from(0, 0, &data, 0, data.len(), max_entries),
+            data,
+            points,
+            encoder,
+        })
+    }
+
+    pub fn encoder(&self) -> &GridEncoder {
+        &self.encoder
     }
 
     pub fn size(&self) -> usize {
-        self.data.len() * 16 + self.root.size()
+        self.data.len() * 24 + self.root.size() + 40
     }
 
-    fn check_bound(&self, offset: usize, lowx: u32, lowy: u32, highx: u32, highy: u32) -> bool {
-        let (x, y) = Point::zvalue_to_raw(self.data[offset]);
-        x >= lowx && x <= highx && y >= lowy && y <= highy
+    fn check_bound(&self, offset: usize, query: &dyn Region) -> bool {
+        query.contains(&self.points[offset])
     }
 
-    fn range_recursive(&self, node: &ZVTreeNode, lowx: u32, lowy: u32, highx: u32, highy: u32, level: u8, res: &mut Vec<Point>) {
+    // Copies nodes whose cells lie inside the query and checks the points of
+    // the others, as cells on the query border may hold points just outside.
+    fn range_recursive(&self, node: &ZVTreeNode, bound: GridBox, level: u32, query: &MBR, res: &mut Vec<Point>) {
+        let (lowx, lowy, highx, highy) = bound;
+        if level == 32 {
+            res.extend(self.points[node.start..node.end].iter().filter(|p| query.contains(p)).cloned());
+            return;
+        }
         let curbit_mask: u32 = 1_u32 << (31 - level);
         let lowbit_mask: u32 = curbit_mask - 1;
         let highbit_mask: u32 = !(curbit_mask | lowbit_mask);
-        assert_eq!(lowx & highbit_mask, highx & highbit_mask);
-        assert_eq!(lowy & highbit_mask, highy & highbit_mask);
         let center_x: u32 = (lowx & highbit_mask) | curbit_mask;
         let center_y: u32 = (lowy & highbit_mask) | curbit_mask;
         if lowx & curbit_mask == 0 && lowx & lowbit_mask == 0 &&
-           highx & curbit_mask != 0 && highx & lowbit_mask == lowbit_mask && 
+           highx & curbit_mask != 0 && highx & lowbit_mask == lowbit_mask &&
            lowy & curbit_mask == 0 && lowy & lowbit_mask == 0 &&
-           highy & curbit_mask != 0 && highy & lowbit_mask == lowbit_mask {
+           highy & curbit_mask != 0 && highy & lowbit_mask == lowbit_mask &&
+           query.contains_mbr(&self.encoder.cell_mbr(lowx, lowy, 1_u64 << (32 - level))) {
 
-            for i in node.start..node.end {
-                res.push(Point::from_zvalue(self.data[i]));
-            }
+            res.extend_from_slice(&self.points[node.start..node.end]);
         } else {
             match &node.children {
                 None => {
-                    for i in node.start..node.end {
-                        if self.check_bound(i, lowx, lowy, highx, highy) {
-                            res.push(Point::from_zvalue(self.data[i]));
-                        }
-                    }
+                    res.extend(self.points[node.start..node.end].iter().filter(|p| query.contains(p)).cloned());
                 }
                 Some((node1, node2, node3, node4)) => {
-                    //  o o
-                    //  x o
                     if lowx & curbit_mask == 0 && lowy & curbit_mask == 0 {
-                        self.range_recursive(&node1, lowx, lowy, (center_x - 1).min(highx) , (center_y - 1).min(highy), level + 1, res);
+                        self.range_recursive(node1, (lowx, lowy, (center_x - 1).min(highx), (center_y - 1).min(highy)), level + 1, query, res);
                     }
-                    //  x o
-                    //  o o
                     if lowx & curbit_mask == 0 && highy & curbit_mask != 0 {
-                        self.range_recursive(&node2, lowx, center_y.max(lowy), (center_x - 1).min(highx), highy, level + 1, res);
+                        self.range_recursive(node2, (lowx, center_y.max(lowy), (center_x - 1).min(highx), highy), level + 1, query, res);
                     }
-                    // o o
-                    // o x
                     if highx & curbit_mask != 0 && lowy & curbit_mask == 0 {
-                        self.range_recursive(&node3, center_x.max(lowx), lowy, highx, (center_y - 1).min(highy), level + 1, res);
+                        self.range_recursive(node3, (center_x.max(lowx), lowy, highx, (center_y - 1).min(highy)), level + 1, query, res);
                     }
-                    // o x
-                    // o o
                     if highx & curbit_mask != 0 && highy & curbit_mask != 0 {
-                        self.range_recursive(&node4, center_x.max(lowx), center_y.max(lowy), highx, highy, level + 1, res);
+                        self.range_recursive(node4, (center_x.max(lowx), center_y.max(lowy), highx, highy), level + 1, query, res);
                     }
                 }
             }
         }
     }
-    
+
     fn range_intervals(&self, node: &ZVTreeNode, lowx: u32, lowy: u32, highx: u32, highy: u32, level: u32, intervals: &mut Vec<(usize, usize)>) {
+        // Nodes of the last level are single cells, e.g. of duplicate points.
+        if level == 32 {
+            if node.end - node.start > 0 {
+                intervals.push((node.start, node.end));
+            }
+            return;
+        }
         let curbit_mask: u32 = 1_u32 << (31 - level);
         let lowbit_mask: u32 = curbit_mask - 1;
         let highbit_mask: u32 = !(curbit_mask | lowbit_mask);
@@ -171,37 +306,143 @@ impl ZVTree {
         }
     }
 
+    pub fn interval_count(&self, query: &MBR) -> usize {
+        let (lowx, lowy, highx, highy) = match self.encoder.scale_query(query) {
+            Some(bound) => bound,
+            None => return 0,
+        };
+        let mut intervals: Vec<(usize, usize)> = Vec::new();
+        self.range_intervals(&self.root, lowx, lowy, highx, highy, 0, &mut intervals);
+        intervals.len()
+    }
+
     pub fn range(&self, query: &MBR) -> Vec<Point> {
         let mut res: Vec<Point> = Vec::new();
-        let (lowx, lowy) = query.low.get_scaled();
-        let (highx, highy) = query.high.get_scaled();
-        self.range_recursive(&self.root, lowx, lowy, highx, highy, 0, &mut res);
+        let bound = match self.encoder.scale_query(query) {
+            Some(bound) => bound,
+            None => return Vec::new(),
+        };
+        self.range_recursive(&self.root, bound, 0, query, &mut res);
         res
     }
 
     pub fn range_sampling(&self, query: &MBR, k: usize) -> Vec<Point> {
-        let (lowx, lowy) = query.low.get_scaled();
-        let (highx, highy) = query.high.get_scaled();
+        let (lowx, lowy, highx, highy) = match self.encoder.scale_query(query) {
+            Some(bound) => bound,
+            None => return Vec::new(),
+        };
         let mut intervals: Vec<(usize, usize)> = Vec::new();
         self.range_intervals(&self.root, lowx, lowy, highx, highy, 0, &mut intervals);
+        self.sample_intervals(query, &intervals, k)
+    }
+
+    // Nodes whose cells intersect the region, where cells of a node are
+    // mapped back to coordinates so that e.g. exclusions can prune them.
+    fn region_intervals(&self, node: &ZVTreeNode, level: u32, x: u32, y: u32, region: &dyn Region, intervals: &mut Vec<(usize, usize)>) {
+        if node.end == node.start {
+            return;
+        }
+        let cell = self.encoder.cell_mbr(x, y, 1_u64 << (32 - level));
+        if !region.intersects(&cell) {
+            return;
+        }
+        match &node.children {
+            Some((node1, node2, node3, node4)) if !region.contains_mbr(&cell) => {
+                let half: u32 = 1_u32 << (31 - level);
+                self.region_intervals(node1, level + 1, x, y, region, intervals);
+                self.region_intervals(node2, level + 1, x, y + half, region, intervals);
+                self.region_intervals(node3, level + 1, x + half, y, region, intervals);
+                self.region_intervals(node4, level + 1, x + half, y + half, region, intervals);
+            }
+            _ => {
+                intervals.push((node.start, node.end));
+            }
+        }
+    }
+
+    pub fn region_range_sampling(&self, region: &dyn Region, k: usize) -> Vec<Point> {
+        let mut intervals: Vec<(usize, usize)> = Vec::new();
+        self.region_intervals(&self.root, 0, 0, 0, region, &mut intervals);
+        if intervals.is_empty() {
+            return Vec::new();
+        }
+        self.sample_intervals(region, &intervals, k)
+    }
+
+    pub fn bigmin_interval_count(&self, query: &MBR, max_intervals: usize) -> usize {
+        match self.encoder.scale_query(query) {
+            Some(bound) => zrange_intervals(bound, max_intervals, &|zmin, zmax| self.search(zmin, zmax)).len(),
+            None => 0,
+        }
+    }
+
+    // Samples from the z-value intervals found by BIGMIN/LITMAX splitting
+    // rather than from the quad structure. `max_intervals` bounds the number of
+    // intervals, usually DEFAULT_BIGMIN_MAX_INTERVALS; usize::MAX asks for the
+    // exact decomposition, which is unbounded on large boxes.
+    pub fn bigmin_range_sampling(&self, query: &MBR, k: usize, max_intervals: usize) -> Vec<Point> {
+        let bound = match self.encoder.scale_query(query) {
+            Some(bound) => bound,
+            None => return Vec::new(),
+        };
+        let intervals = zrange_intervals(bound, max_intervals, &|zmin, zmax| self.search(zmin, zmax));
+        self.sample_intervals(query, &intervals, k)
+    }
+
+    fn search(&self, zmin: u64, zmax: u64) -> (usize, usize) {
+        (self.data.lower_bound(&zmin), self.data.upper_bound(&zmax))
+    }
 
+    fn sample_intervals(&self, query: &dyn Region, intervals: &[(usize, usize)], k: usize) -> Vec<Point> {
+        let population: usize = intervals.iter().map(|(start, end)| end - start).sum();
+        if population == 0 {
+            return Vec::new();
+        }
         let weights: Vec<f64> = intervals.iter().map(|(x, y)| (y - x) as f64).collect();
         let dist = Uniform::from(0.0f64..1.0f64);
         let mut rng = util::new_rng();
         let top_level_alias = AliasTable::from(&weights);
         let mut samples: Vec<Point> = Vec::new();
+        let mut rejections = 0;
         // For each sample, construct two level sampling.
         while samples.len() < k {
             let coin1 = dist.sample(&mut rng);
             let coin2 = dist.sample(&mut rng);
             let res = top_level_alias.sample(coin1, coin2);
             let coin3 = dist.sample(&mut rng);
-            let offset = (weights[res] as f64 * coin3) as usize  + intervals[res].0;
-            if self.check_bound(offset, lowx, lowy, highx, highy) {
-                samples.push(Point::from_zvalue(self.data[offset]));
+            let offset = (weights[res] * coin3) as usize  + intervals[res].0;
+            if self.check_bound(offset, query) {
+                samples.push(self.points[offset].clone());
+            } else {
+                rejections += 1;
+                let holds_any = || util::holds_any(query, intervals.iter().map(|(start, end)| &self.points[*start..*end]));
+                if util::gives_up(rejections, population, holds_any) { break; }
             }
         }
         samples
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::MBRDifference;
+
+    fn lattice(side: usize) -> Vec<Point> {
+        (0..side * side).map(|i| Point::new((i % side) as f64, (i / side) as f64)).collect()
+    }
+
+    #[test]
+    fn difference_samples_avoid_holes() {
+        let tree = ZVTree::from(&lattice(40));
+        let holes = MBRDifference::new(&MBR::new(&Point::new(4.5, 4.5), &Point::new(30.5, 30.5)),
+                                       &[MBR::new(&Point::new(5.5, 5.5), &Point::new(20.5, 20.5))]);
+        let samples = tree.region_range_sampling(&holes, 2000);
+        assert_eq!(samples.len(), 2000);
+        assert!(samples.iter().all(|p| holes.contains(p)));
+        let covered = MBRDifference::new(&MBR::new(&Point::new(4.5, 4.5), &Point::new(30.5, 30.5)),
+                                         &[MBR::new(&Point::new(4.0, 4.0), &Point::new(31.0, 31.0))]);
+        assert!(tree.region_range_sampling(&covered, 10).is_empty());
+    }
+}
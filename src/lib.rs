@@ -2,3 +2,12 @@ pub mod geo;
 pub mod util;
 pub mod alias;
 pub mod index;
+pub mod stratified;
+pub mod progressive;
+pub mod loader;
+pub mod geojson;
+pub mod pointfile;
+pub mod workload;
+pub mod bench;
+#[cfg(feature = "arrow")]
+pub mod arrow;
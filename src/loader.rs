@@ -0,0 +1,320 @@
+use crate::geo::{Geometry, MBR, Point};
+use crate::pointfile::{self, PointFile};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// Layout of a text line: delimited columns, or one WKT or hex WKB geometry.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TextFormat {
+    Delimited,
+    Geometry,
+}
+
+// Reads points from delimited text, one point per line.
+#[derive(Clone, Debug)]
+pub struct PointLoader {
+    format: TextFormat,
+    delimiter: char,
+    x_column: usize,
+    y_column: usize,
+    skip_lines: usize,
+}
+
+// A line that could not be parsed, numbered from 1.
+#[derive(PartialEq, Clone, Debug)]
+pub struct BadLine {
+    pub line: usize,
+    pub reason: String,
+}
+
+pub struct LoadResult {
+    pub points: Vec<Point>,
+    pub bad_lines: Vec<BadLine>,
+}
+
+// Lines of text, like BufRead::lines, except that a line of invalid UTF-8
+// yields the reason for a bad line instead of an error that ends the read.
+struct TextLines<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Iterator for TextLines<R> {
+    type Item = std::io::Result<Result<String, String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line: Vec<u8> = Vec::new();
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if line.ends_with(b"\r") { line.pop(); }
+                }
+                Some(Ok(String::from_utf8(line).map_err(|err| err.utf8_error().to_string())))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl fmt::Display for BadLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl PointLoader {
+    pub fn new() -> PointLoader {
+        PointLoader {
+            format: TextFormat::Delimited,
+            delimiter: ' ',
+            x_column: 0,
+            y_column: 1,
+            skip_lines: 0,
+        }
+    }
+
+    pub fn format(mut self, format: TextFormat) -> PointLoader {
+        self.format = format;
+        self
+    }
+
+    // A space delimiter splits on runs of whitespace.
+    pub fn delimiter(mut self, delimiter: char) -> PointLoader {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn columns(mut self, x_column: usize, y_column: usize) -> PointLoader {
+        self.x_column = x_column;
+        self.y_column = y_column;
+        self
+    }
+
+    // Number of header lines to skip.
+    pub fn skip_lines(mut self, skip_lines: usize) -> PointLoader {
+        self.skip_lines = skip_lines;
+        self
+    }
+
+    // Binary point files, and Arrow IPC files and streams with the arrow
+    // feature, are recognized by their first bytes and loaded as is.
+    pub fn load(&self, path: &str) -> std::io::Result<LoadResult> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if reader.fill_buf()?.starts_with(pointfile::MAGIC) {
+            return Ok(LoadResult {
+                points: PointFile::load(path)?.points,
+                bad_lines: Vec::new(),
+            });
+        }
+        if is_arrow(reader.fill_buf()?) {
+            return self.load_arrow(reader);
+        }
+        self.read(reader)
+    }
+
+    #[cfg(feature = "arrow")]
+    fn load_arrow(&self, reader: BufReader<File>) -> std::io::Result<LoadResult> {
+        let arrow = crate::arrow::ArrowPoints::read_columns(reader, self.x_column, self.y_column)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        let bad_lines = arrow.skipped.iter().map(|row| BadLine { line: row + 1, reason: "null or non-finite coordinates".to_string() }).collect();
+        Ok(LoadResult {
+            points: arrow.points,
+            bad_lines,
+        })
+    }
+
+    #[cfg(not(feature = "arrow"))]
+    fn load_arrow(&self, _reader: BufReader<File>) -> std::io::Result<LoadResult> {
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Arrow IPC input needs the arrow feature"))
+    }
+
+    // Empty lines are ignored; lines that fail to parse are collected instead
+    // of aborting the load.
+    pub fn read<R: BufRead>(&self, reader: R) -> std::io::Result<LoadResult> {
+        let mut points: Vec<Point> = Vec::new();
+        let mut bad_lines: Vec<BadLine> = Vec::new();
+        for (i, line) in (TextLines { reader }).enumerate().skip(self.skip_lines) {
+            let line = match line? {
+                Ok(line) => line,
+                Err(reason) => {
+                    bad_lines.push(BadLine { line: i + 1, reason });
+                    continue;
+                }
+            };
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            match self.parse_line(line) {
+                Ok(p) => points.push(p),
+                Err(reason) => bad_lines.push(BadLine { line: i + 1, reason }),
+            }
+        }
+        Ok(LoadResult {
+            points,
+            bad_lines,
+        })
+    }
+
+    pub fn parse_line(&self, line: &str) -> Result<Point, String> {
+        if self.format == TextFormat::Geometry {
+            return match parse_geometry(line)? {
+                Geometry::Point(p) if p.x.is_finite() && p.y.is_finite() => Ok(p),
+                Geometry::Point(_) => Err("non-finite point".to_string()),
+                _ => Err("expected a POINT geometry".to_string()),
+            };
+        }
+        let fields: Vec<&str> = if self.delimiter == ' ' {
+            line.split_whitespace().collect()
+        } else {
+            line.split(self.delimiter).map(|field| field.trim()).collect()
+        };
+        let x = PointLoader::parse_field(&fields, self.x_column)?;
+        let y = PointLoader::parse_field(&fields, self.y_column)?;
+        Ok(Point { x, y })
+    }
+
+    fn parse_field(fields: &[&str], column: usize) -> Result<f64, String> {
+        let field = fields.get(column)
+            .ok_or_else(|| format!("expected at least {} columns, found {}", column + 1, fields.len()))?;
+        match field.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            Ok(_) => Err(format!("non-finite value {:?} in column {}", field, column)),
+            Err(_) => Err(format!("invalid number {:?} in column {}", field, column)),
+        }
+    }
+}
+
+// Arrow IPC files start with ARROW1 and streams with the 0xFFFFFFFF
+// continuation marker of their schema message, neither of which is valid
+// text. Streams written before Arrow 0.15 lack the marker and are read as text.
+fn is_arrow(head: &[u8]) -> bool {
+    head.starts_with(b"ARROW1") || head.starts_with(&[0xFF, 0xFF, 0xFF, 0xFF])
+}
+
+// Lines made only of hex digits are read as WKB, others as WKT.
+pub fn parse_geometry(line: &str) -> Result<Geometry, String> {
+    let line = line.trim();
+    let geometry = if line.bytes().all(|c| c.is_ascii_hexdigit()) {
+        Geometry::from_wkb_hex(line)
+    } else {
+        Geometry::from_wkt(line)
+    };
+    geometry.map_err(|err| err.to_string())
+}
+
+// Reads query shapes, one WKT or hex WKB geometry per line.
+pub fn load_geometries(path: &str) -> std::io::Result<(Vec<Geometry>, Vec<BadLine>)> {
+    let mut geometries: Vec<Geometry> = Vec::new();
+    let mut bad_lines: Vec<BadLine> = Vec::new();
+    for (i, line) in (TextLines { reader: BufReader::new(File::open(path)?) }).enumerate() {
+        let line = match line? {
+            Ok(line) => line,
+            Err(reason) => {
+                bad_lines.push(BadLine { line: i + 1, reason });
+                continue;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_geometry(&line) {
+            Ok(geometry) => geometries.push(geometry),
+            Err(reason) => bad_lines.push(BadLine { line: i + 1, reason }),
+        }
+    }
+    Ok((geometries, bad_lines))
+}
+
+// Reads query rectangles, one "x1 y1 x2 y2" per line; further columns, e.g.
+// the counts written by workload::write_queries, are ignored.
+pub fn load_ranges(path: &str) -> std::io::Result<(Vec<MBR>, Vec<BadLine>)> {
+    let low = PointLoader::new();
+    let high = PointLoader::new().columns(2, 3);
+    let mut ranges: Vec<MBR> = Vec::new();
+    let mut bad_lines: Vec<BadLine> = Vec::new();
+    for (i, line) in (TextLines { reader: BufReader::new(File::open(path)?) }).enumerate() {
+        let line = match line? {
+            Ok(line) => line,
+            Err(reason) => {
+                bad_lines.push(BadLine { line: i + 1, reason });
+                continue;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match (low.parse_line(&line), high.parse_line(&line)) {
+            (Ok(p1), Ok(p2)) => ranges.push(MBR::from_points(&[p1, p2])),
+            (Err(reason), _) | (_, Err(reason)) => bad_lines.push(BadLine { line: i + 1, reason }),
+        }
+    }
+    Ok((ranges, bad_lines))
+}
+
+impl Default for PointLoader {
+    fn default() -> PointLoader {
+        PointLoader::new()
+    }
+}
+
+impl LoadResult {
+    // Prints the bad lines to stderr and returns the points.
+    pub fn report(self, path: &str) -> Vec<Point> {
+        for bad_line in self.bad_lines.iter() {
+            eprintln!("{}: skipped {}", path, bad_line);
+        }
+        self.points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_columns_without_trailing_newline() {
+        let text = "id x y\n7 1.5 2.25\n8  -3 4e2\r\n\n9 0.5 0.125";
+        let result = PointLoader::new().columns(1, 2).skip_lines(1).read(text.as_bytes()).unwrap();
+        assert_eq!(result.points, vec![Point::new(1.5, 2.25), Point::new(-3.0, 400.0), Point::new(0.5, 0.125)]);
+        assert!(result.bad_lines.is_empty());
+    }
+
+    #[test]
+    fn reports_bad_lines_by_number() {
+        let text = "x,y\n1,2\n3\n4,abc\n5,inf\n6 , 7\n";
+        let result = PointLoader::new().delimiter(',').skip_lines(1).read(text.as_bytes()).unwrap();
+        assert_eq!(result.points, vec![Point::new(1.0, 2.0), Point::new(6.0, 7.0)]);
+        let lines: Vec<usize> = result.bad_lines.iter().map(|bad_line| bad_line.line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+        assert_eq!(result.bad_lines[0].reason, "expected at least 2 columns, found 1");
+    }
+
+    #[test]
+    fn reports_invalid_utf8_as_bad_lines() {
+        let text: &[u8] = b"1 2\n3 \xff\n4 5\r\n";
+        let result = PointLoader::new().read(text).unwrap();
+        assert_eq!(result.points, vec![Point::new(1.0, 2.0), Point::new(4.0, 5.0)]);
+        assert_eq!(result.bad_lines.len(), 1);
+        assert_eq!(result.bad_lines[0].line, 2);
+    }
+
+    #[test]
+    fn reads_geometry_lines() {
+        let text = "POINT (1 2)\n0101000000000000000000F03F0000000000000040\nLINESTRING (0 0, 1 1)\n";
+        let result = PointLoader::new().format(TextFormat::Geometry).read(text.as_bytes()).unwrap();
+        assert_eq!(result.points, vec![Point::new(1.0, 2.0), Point::new(1.0, 2.0)]);
+        assert_eq!(result.bad_lines.len(), 1);
+        assert_eq!(result.bad_lines[0].line, 3);
+    }
+
+    #[test]
+    fn recognizes_arrow_input() {
+        assert!(is_arrow(b"ARROW1\0\0"));
+        assert!(is_arrow(&[0xFF, 0xFF, 0xFF, 0xFF, 0x10, 0x01, 0x00, 0x00]));
+        assert!(!is_arrow(b"1.0 2.0\n"));
+        assert!(!is_arrow(b""));
+    }
+}
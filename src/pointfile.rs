@@ -0,0 +1,198 @@
+use crate::geo::{MBR, Point};
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Read, Write};
+
+// Binary point files, all little-endian:
+//   magic "RSPT", version u32, flags u32, reserved u32, count u64,
+//   bounding box as low.x, low.y, high.x, high.y f64,
+// followed by count (x, y) f64 pairs, then count u64 ids if FLAG_IDS is set
+// and count f64 weights if FLAG_WEIGHTS is set.
+pub const MAGIC: &[u8; 4] = b"RSPT";
+const VERSION: u32 = 1;
+const FLAG_IDS: u32 = 1;
+const FLAG_WEIGHTS: u32 = 2;
+const HEADER_SIZE: usize = 56;
+// Values decoded per read call.
+const CHUNK_SIZE: usize = 1 << 20;
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct PointFileHeader {
+    pub count: usize,
+    pub bounding_box: MBR,
+    pub has_ids: bool,
+    pub has_weights: bool,
+}
+
+pub struct PointFile {
+    pub bounding_box: MBR,
+    pub points: Vec<Point>,
+    pub ids: Option<Vec<u64>>,
+    pub weights: Option<Vec<f64>>,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0_u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0_u8; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn f64_at(buf: &[u8], offset: usize) -> f64 {
+    f64::from_bits(u64_at(buf, offset))
+}
+
+// Reads `count` values of `width` bytes each, CHUNK_SIZE values at a time.
+// The count comes from an untrusted header, so the values grow chunk by chunk
+// and a short input is invalid data.
+fn read_values<R: Read, T, F: Fn(&[u8]) -> T>(reader: &mut R, count: usize, width: usize, decode: F) -> std::io::Result<Vec<T>> {
+    let mut values: Vec<T> = Vec::with_capacity(CHUNK_SIZE.min(count));
+    let mut buf: Vec<u8> = vec![0; CHUNK_SIZE.min(count) * width];
+    while values.len() < count {
+        let len = (count - values.len()).min(CHUNK_SIZE) * width;
+        reader.read_exact(&mut buf[..len]).map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => invalid("point file is shorter than its header count"),
+            _ => err,
+        })?;
+        values.extend(buf[..len].chunks_exact(width).map(&decode));
+    }
+    Ok(values)
+}
+
+impl PointFileHeader {
+    pub fn read<R: Read>(reader: &mut R) -> std::io::Result<PointFileHeader> {
+        let mut buf = [0_u8; HEADER_SIZE];
+        reader.read_exact(&mut buf)?;
+        if &buf[0..4] != MAGIC {
+            return Err(invalid("not a point file"));
+        }
+        if u32_at(&buf, 4) != VERSION {
+            return Err(invalid("unsupported point file version"));
+        }
+        let flags = u32_at(&buf, 8);
+        Ok(PointFileHeader {
+            count: u64_at(&buf, 16) as usize,
+            bounding_box: MBR::new(&Point::new(f64_at(&buf, 24), f64_at(&buf, 32)),
+                                   &Point::new(f64_at(&buf, 40), f64_at(&buf, 48))),
+            has_ids: flags & FLAG_IDS != 0,
+            has_weights: flags & FLAG_WEIGHTS != 0,
+        })
+    }
+
+    pub fn file_size(&self) -> u64 {
+        let width: u64 = 16 + if self.has_ids { 8 } else { 0 } + if self.has_weights { 8 } else { 0 };
+        (self.count as u64).saturating_mul(width).saturating_add(HEADER_SIZE as u64)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut flags: u32 = 0;
+        if self.has_ids { flags |= FLAG_IDS; }
+        if self.has_weights { flags |= FLAG_WEIGHTS; }
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&flags.to_le_bytes())?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+        writer.write_all(&(self.count as u64).to_le_bytes())?;
+        for v in [self.bounding_box.low.x, self.bounding_box.low.y, self.bounding_box.high.x, self.bounding_box.high.y].iter() {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl PointFile {
+    pub fn load(path: &str) -> std::io::Result<PointFile> {
+        let mut file = File::open(path)?;
+        let header = PointFileHeader::read(&mut file)?;
+        // Check the size first, as a corrupt count would allocate that many points.
+        if file.metadata()?.len() != header.file_size() {
+            return Err(invalid("point file size does not match its header"));
+        }
+        PointFile::read_body(&mut file, header)
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> std::io::Result<PointFile> {
+        let header = PointFileHeader::read(reader)?;
+        PointFile::read_body(reader, header)
+    }
+
+    fn read_body<R: Read>(reader: &mut R, header: PointFileHeader) -> std::io::Result<PointFile> {
+        let points = read_values(reader, header.count, 16, |bytes| Point { x: f64_at(bytes, 0), y: f64_at(bytes, 8) })?;
+        let ids = if header.has_ids { Some(read_values(reader, header.count, 8, |bytes| u64_at(bytes, 0))?) } else { None };
+        let weights = if header.has_weights { Some(read_values(reader, header.count, 8, |bytes| f64_at(bytes, 0))?) } else { None };
+        Ok(PointFile {
+            bounding_box: header.bounding_box,
+            points,
+            ids,
+            weights,
+        })
+    }
+
+    pub fn save(path: &str, points: &[Point], ids: Option<&[u64]>, weights: Option<&[f64]>) -> std::io::Result<()> {
+        PointFile::write(&mut BufWriter::new(File::create(path)?), points, ids, weights)
+    }
+
+    pub fn write<W: Write>(writer: &mut W, points: &[Point], ids: Option<&[u64]>, weights: Option<&[f64]>) -> std::io::Result<()> {
+        if ids.map_or(false, |ids| ids.len() != points.len()) || weights.map_or(false, |weights| weights.len() != points.len()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "expect one id and weight per point"));
+        }
+        let header = PointFileHeader {
+            count: points.len(),
+            bounding_box: MBR::from_points(points),
+            has_ids: ids.is_some(),
+            has_weights: weights.is_some(),
+        };
+        header.write(writer)?;
+        for p in points.iter() {
+            writer.write_all(&p.x.to_le_bytes())?;
+            writer.write_all(&p.y.to_le_bytes())?;
+        }
+        for id in ids.unwrap_or(&[]).iter() {
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        for weight in weights.unwrap_or(&[]).iter() {
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_with_columns() {
+        let points = vec![Point::new(1.0, 2.0), Point::new(-3.5, 0.25), Point::new(7.0, -8.0)];
+        let mut buffer: Vec<u8> = Vec::new();
+        PointFile::write(&mut buffer, &points, Some(&[3, 1, 2]), Some(&[0.5, 1.0, 2.0])).unwrap();
+        let file = PointFile::read(&mut buffer.as_slice()).unwrap();
+        assert_eq!(file.points, points);
+        assert_eq!(file.ids, Some(vec![3, 1, 2]));
+        assert_eq!(file.weights, Some(vec![0.5, 1.0, 2.0]));
+        assert_eq!(file.bounding_box, MBR::from_points(&points));
+        let header = PointFileHeader::read(&mut buffer.as_slice()).unwrap();
+        assert_eq!(header.file_size(), buffer.len() as u64);
+    }
+
+    #[test]
+    fn rejects_counts_beyond_the_input() {
+        let mut buffer: Vec<u8> = Vec::new();
+        PointFile::write(&mut buffer, &[Point::new(1.0, 2.0)], None, None).unwrap();
+        // A header claiming 2^60 points must not allocate them up front.
+        buffer[16..24].copy_from_slice(&(1_u64 << 60).to_le_bytes());
+        let err = PointFile::read(&mut buffer.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        buffer.truncate(HEADER_SIZE + 8);
+        buffer[16..24].copy_from_slice(&1_u64.to_le_bytes());
+        assert_eq!(PointFile::read(&mut buffer.as_slice()).err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+}
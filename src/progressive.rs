@@ -0,0 +1,176 @@
+// Online aggregation over rejection sampling: running estimates of a range
+// count and of the mean of an attribute, with confidence intervals that shrink
+// as samples accumulate. KDTree::progressive_range_sampling is the only
+// sampler that reports them so far.
+use std::time::{Duration, Instant};
+
+// Normal quantile of the reported two-sided 95% confidence intervals.
+const CONFIDENCE_Z: f64 = 1.96;
+
+// When progressive sampling stops, whichever criterion is reached first. It is
+// built from one criterion and extended with others, so that it always has one.
+#[derive(Clone, Debug)]
+pub struct StopCondition {
+    // Relative half-width of the confidence intervals of both the count and
+    // the mean. A mean at or near zero has no useful relative error, so its
+    // interval is also measured against the spread of the values.
+    target_error: Option<f64>,
+    max_samples: Option<usize>,
+    time_budget: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    pub trials: usize,
+    pub samples: usize,
+    pub count: f64,
+    pub count_error: f64,
+    pub mean: f64,
+    pub mean_error: f64,
+    pub elapsed: Duration,
+}
+
+// Running estimates over rejection sampling trials drawn uniformly from
+// candidates holding `candidate_size` points: accepted trials estimate the
+// count as a binomial proportion of the candidates, and their values feed a
+// Welford mean and variance.
+pub struct OnlineAggregator {
+    candidate_size: f64,
+    trials: usize,
+    samples: usize,
+    mean: f64,
+    m2: f64,
+    start: Instant,
+}
+
+impl StopCondition {
+    pub fn target_error(target_error: f64) -> StopCondition {
+        StopCondition { target_error: None, max_samples: None, time_budget: None }.or_target_error(target_error)
+    }
+
+    pub fn max_samples(max_samples: usize) -> StopCondition {
+        StopCondition { target_error: None, max_samples: Some(max_samples), time_budget: None }
+    }
+
+    pub fn time_budget(time_budget: Duration) -> StopCondition {
+        StopCondition { target_error: None, max_samples: None, time_budget: Some(time_budget) }
+    }
+
+    pub fn or_target_error(mut self, target_error: f64) -> StopCondition {
+        assert!(target_error > 0.0, "target error must be positive");
+        self.target_error = Some(target_error);
+        self
+    }
+
+    pub fn or_max_samples(mut self, max_samples: usize) -> StopCondition {
+        self.max_samples = Some(max_samples);
+        self
+    }
+
+    pub fn or_time_budget(mut self, time_budget: Duration) -> StopCondition {
+        self.time_budget = Some(time_budget);
+        self
+    }
+
+    pub fn reached(&self, estimate: &Estimate) -> bool {
+        if let Some(max_samples) = self.max_samples {
+            if estimate.samples >= max_samples { return true; }
+        }
+        if let Some(time_budget) = self.time_budget {
+            if estimate.elapsed >= time_budget { return true; }
+        }
+        if let Some(target_error) = self.target_error {
+            let spread = estimate.mean_error * (estimate.samples as f64).sqrt() / CONFIDENCE_Z;
+            if estimate.samples > 1 && estimate.count_error <= target_error * estimate.count &&
+               estimate.mean_error <= target_error * estimate.mean.abs().max(spread) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl OnlineAggregator {
+    pub fn new(candidate_size: f64) -> OnlineAggregator {
+        OnlineAggregator {
+            candidate_size,
+            trials: 0,
+            samples: 0,
+            mean: 0.0,
+            m2: 0.0,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn reject(&mut self) {
+        self.trials += 1;
+    }
+
+    pub fn accept(&mut self, value: f64) {
+        self.trials += 1;
+        self.samples += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.samples as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn estimate(&self) -> Estimate {
+        let (count, count_error) = if self.trials == 0 {
+            (0.0, self.candidate_size)
+        } else {
+            let p = self.samples as f64 / self.trials as f64;
+            (self.candidate_size * p, CONFIDENCE_Z * self.candidate_size * (p * (1.0 - p) / self.trials as f64).sqrt())
+        };
+        let mean_error = if self.samples > 1 {
+            CONFIDENCE_Z * (self.m2 / (self.samples - 1) as f64 / self.samples as f64).sqrt()
+        } else {
+            f64::INFINITY
+        };
+        Estimate {
+            trials: self.trials,
+            samples: self.samples,
+            count,
+            count_error,
+            mean: self.mean,
+            mean_error,
+            elapsed: self.start.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(samples: usize, count_error: f64, elapsed: Duration) -> Estimate {
+        Estimate { trials: samples * 2, samples, count: 100.0, count_error, mean: 10.0, mean_error: 0.1, elapsed }
+    }
+
+    #[test]
+    fn stops_on_any_criterion() {
+        let stop = StopCondition::max_samples(10).or_time_budget(Duration::from_secs(1)).or_target_error(0.05);
+        assert!(!stop.reached(&estimate(5, 50.0, Duration::from_millis(10))));
+        assert!(stop.reached(&estimate(10, 50.0, Duration::from_millis(10))));
+        assert!(stop.reached(&estimate(5, 50.0, Duration::from_secs(2))));
+        assert!(stop.reached(&estimate(5, 4.0, Duration::from_millis(10))));
+    }
+
+    #[test]
+    fn stops_on_a_zero_mean() {
+        let stop = StopCondition::target_error(0.05);
+        let mut aggregator = OnlineAggregator::new(100.0);
+        for i in 0..3000 {
+            aggregator.accept(if i % 2 == 0 { 1.0 } else { -1.0 });
+            if i == 99 { assert!(!stop.reached(&aggregator.estimate())); }
+        }
+        let estimate = aggregator.estimate();
+        assert!(estimate.mean.abs() < 1e-9);
+        assert!(stop.reached(&estimate));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_non_positive_target_error() {
+        StopCondition::target_error(0.0);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::geo::{MBR, Point};
+
+pub enum Allocation {
+    Equal,
+    Proportional,
+    // Neyman allocation from a hint of the standard deviation of each stratum.
+    Neyman(Vec<f64>),
+}
+
+pub struct StratifiedSample {
+    pub point: Point,
+    pub stratum: usize,
+    pub stratum_size: f64,
+}
+
+// Largest f64 below x, so that adjacent strata do not share their border.
+fn prev_float(x: f64) -> f64 {
+    if x.is_nan() || x == f64::NEG_INFINITY {
+        x
+    } else if x == 0.0 {
+        -f64::from_bits(1)
+    } else if x > 0.0 {
+        f64::from_bits(x.to_bits() - 1)
+    } else {
+        f64::from_bits(x.to_bits() + 1)
+    }
+}
+
+// Splits the query into nx * ny disjoint strata covering it exactly, numbered
+// i * ny + j for the i-th column and j-th row.
+pub fn strata(query: &MBR, nx: usize, ny: usize) -> Vec<MBR> {
+    assert!(nx > 0 && ny > 0);
+    let bounds = |low: f64, high: f64, n: usize| -> Vec<f64> {
+        let mut res: Vec<f64> = (0..n).map(|i| low + (high - low) * i as f64 / n as f64).collect();
+        res.push(high);
+        res
+    };
+    let xs = bounds(query.low.x, query.high.x, nx);
+    let ys = bounds(query.low.y, query.high.y, ny);
+    let mut res: Vec<MBR> = Vec::new();
+    for i in 0..nx {
+        for j in 0..ny {
+            let high_x = if i + 1 == nx { xs[i + 1] } else { prev_float(xs[i + 1]) };
+            let high_y = if j + 1 == ny { ys[j + 1] } else { prev_float(ys[j + 1]) };
+            res.push(MBR::new(&Point::new(xs[i], ys[j]), &Point::new(high_x, high_y)));
+        }
+    }
+    res
+}
+
+// Splits k samples among strata of the given sizes, rounding by largest
+// remainder. Empty strata get no samples.
+pub fn allocate(sizes: &[f64], k: usize, allocation: &Allocation) -> Vec<usize> {
+    let mut weights: Vec<f64> = match allocation {
+        Allocation::Equal => sizes.iter().map(|size| if *size > 0.0 { 1.0 } else { 0.0 }).collect(),
+        Allocation::Proportional => sizes.to_vec(),
+        Allocation::Neyman(deviations) => {
+            assert_eq!(deviations.len(), sizes.len());
+            sizes.iter().zip(deviations.iter()).map(|(size, deviation)| size * deviation).collect()
+        }
+    };
+    let mut sum_weight: f64 = weights.iter().sum();
+    if sum_weight <= 0.0 {
+        // No variance hint is positive, fall back to proportional allocation.
+        weights = sizes.to_vec();
+        sum_weight = weights.iter().sum();
+    }
+    let mut res: Vec<usize> = vec![0; sizes.len()];
+    if sum_weight <= 0.0 {
+        return res;
+    }
+    let quotas: Vec<f64> = weights.iter().map(|weight| k as f64 * weight / sum_weight).collect();
+    let mut assigned: usize = 0;
+    for (n, quota) in res.iter_mut().zip(quotas.iter()) {
+        *n = quota.floor() as usize;
+        assigned += *n;
+    }
+    let mut order: Vec<usize> = (0..sizes.len()).filter(|i| weights[*i] > 0.0).collect();
+    order.sort_by(|a, b| (quotas[*b] - quotas[*b].floor()).partial_cmp(&(quotas[*a] - quotas[*a].floor())).unwrap());
+    for i in order.iter().cycle().take(k.saturating_sub(assigned)) {
+        res[*i] += 1;
+    }
+    res
+}
+
+// Stratified range sampling over an nx * ny grid of the query. `count` gives
+// the exact or estimated number of points of a stratum and `sample` draws
+// uniform samples from it, e.g. `|q| tree.range_count(q) as f64` and
+// `|q, k| tree.range_sampling(q, k)`.
+pub fn stratified_sampling(query: &MBR, nx: usize, ny: usize, k: usize, allocation: &Allocation,
+                           count: &dyn Fn(&MBR) -> f64,
+                           sample: &mut dyn FnMut(&MBR, usize) -> Vec<Point>) -> Vec<StratifiedSample> {
+    let strata = strata(query, nx, ny);
+    let sizes: Vec<f64> = strata.iter().map(count).collect();
+    let budget = allocate(&sizes, k, allocation);
+    let mut samples: Vec<StratifiedSample> = Vec::new();
+    for (i, stratum) in strata.iter().enumerate() {
+        if budget[i] == 0 {
+            continue;
+        }
+        for point in sample(stratum, budget[i]).into_iter() {
+            samples.push(StratifiedSample {
+                point,
+                stratum: i,
+                stratum_size: sizes[i],
+            });
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lattice(n: usize) -> Vec<Point> {
+        let mut res: Vec<Point> = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                res.push(Point::new(i as f64, j as f64));
+            }
+        }
+        res
+    }
+
+    fn scan_count(points: &[Point], query: &MBR) -> usize {
+        points.iter().filter(|p| query.contains(p)).count()
+    }
+
+    #[test]
+    fn strata_partition_the_query() {
+        // Stratum borders fall on lattice points, which must land in exactly one stratum.
+        let points = lattice(21);
+        let query = MBR::new(&Point::new(0.0, 0.0), &Point::new(20.0, 20.0));
+        let strata = strata(&query, 4, 5);
+        assert_eq!(strata.len(), 20);
+        for p in points.iter() {
+            assert_eq!(strata.iter().filter(|s| s.contains(p)).count(), 1);
+        }
+        let total: usize = strata.iter().map(|s| scan_count(&points, s)).sum();
+        assert_eq!(total, scan_count(&points, &query));
+    }
+
+    #[test]
+    fn allocation_spends_the_budget() {
+        let sizes = vec![10.0, 0.0, 30.0, 60.0];
+        for allocation in [Allocation::Equal, Allocation::Proportional, Allocation::Neyman(vec![1.0, 5.0, 2.0, 0.5])].iter() {
+            let budget = allocate(&sizes, 101, allocation);
+            assert_eq!(budget.iter().sum::<usize>(), 101);
+            assert_eq!(budget[1], 0);
+        }
+        assert_eq!(allocate(&sizes, 100, &Allocation::Proportional), vec![10, 0, 30, 60]);
+        assert_eq!(allocate(&[0.0, 0.0], 10, &Allocation::Equal), vec![0, 0]);
+    }
+
+    #[test]
+    fn samples_carry_exact_stratum_sizes() {
+        let points = lattice(30);
+        let query = MBR::new(&Point::new(2.5, 2.5), &Point::new(27.5, 17.5));
+        let strata = strata(&query, 3, 2);
+        let samples = stratified_sampling(&query, 3, 2, 600, &Allocation::Proportional,
+            &|q| scan_count(&points, q) as f64,
+            &mut |q, k| {
+                let inside: Vec<Point> = points.iter().filter(|p| q.contains(p)).cloned().collect();
+                crate::util::sample_from(&inside, k)
+            });
+        assert_eq!(samples.len(), 600);
+        for sample in samples.iter() {
+            assert!(strata[sample.stratum].contains(&sample.point));
+            assert_eq!(sample.stratum_size, scan_count(&points, &strata[sample.stratum]) as f64);
+        }
+    }
+}
@@ -13,6 +13,11 @@ pub fn new_rng() -> impl rand::RngCore {
     RNG::from_entropy()
 }
 
+// Same generator with a fixed seed, for reproducible runs.
+pub fn seeded_rng(seed: u64) -> impl rand::RngCore {
+    RNG::seed_from_u64(seed)
+}
+
 #[inline(always)]
 pub fn approx_median<T>(data: &[T], k: usize, f : &dyn Fn(&T) -> f64) -> f64 {
     let mut rng = new_rng();
@@ -53,9 +58,25 @@ pub fn partition_around<T>(points: &mut [T], f : &dyn Fn(&T) -> bool) -> usize {
     }
 }
 
+// Rejection samplers never finish on a range without points. Rather than
+// scanning their candidates before sampling, they count rejections and look
+// once, when the rejections reach the number of points they draw from: ranges
+// with points seldom get there, and by then the look costs no more than the
+// draws already made.
+pub fn gives_up(rejections: usize, population: usize, holds_any: impl FnOnce() -> bool) -> bool {
+    rejections == population.max(1) && !holds_any()
+}
+
+pub fn holds_any<'a, I: IntoIterator<Item = &'a [geo::Point]>>(region: &dyn geo::Region, slices: I) -> bool {
+    slices.into_iter().any(|slice| slice.iter().any(|p| region.contains(p)))
+}
+
 #[inline(always)]
 pub fn sample_from<T: Clone>(data: &[T], k: usize) -> Vec<T> {
     let mut samples: Vec<T> = Vec::new();
+    if data.is_empty() {
+        return samples;
+    }
     let mut rng = new_rng();
     let dist = Uniform::from(0.0f64..1.0f64);
     let len = data.len();
@@ -68,9 +89,26 @@ pub fn sample_from<T: Clone>(data: &[T], k: usize) -> Vec<T> {
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub input_file: String,
+    #[serde(default)]
     pub ranges: Vec<geo::MBR>,
+    // Query files as written by the workload generator; every file, like
+    // `ranges`, is benchmarked as a workload of its own.
+    #[serde(default)]
+    pub range_files: Vec<String>,
     pub k_values: Vec<usize>,
     pub methods: Vec<String>,
+    // Interval budget of the BIGMIN/LITMAX z-value decomposition ("zvb"),
+    // zvtree::DEFAULT_BIGMIN_MAX_INTERVALS (256) if not given.
+    #[serde(default)]
+    pub max_intervals: Option<usize>,
+    // Untimed passes over the queries before the timed repetitions.
+    #[serde(default)]
+    pub warmup: usize,
+    #[serde(default)]
+    pub repetitions: Option<usize>,
+    // Results file, JSON if it ends in .json and CSV otherwise.
+    #[serde(default)]
+    pub output: Option<String>,
 } 
 
 pub struct SampleQuery {
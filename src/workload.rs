@@ -0,0 +1,233 @@
+use crate::geo::{MBR, Point};
+use crate::alias::AliasTable;
+use crate::index::kdtree::KDTree;
+use crate::util::{self, SampleQuery};
+use rand::distributions::{Uniform, Distribution};
+use std::fmt;
+use std::io::Write;
+
+const DEFAULT_SELECTIVITY: usize = 1000;
+const DEFAULT_HOTSPOT_SPREAD: f64 = 0.01;
+// Bisection steps when fitting a query to the target selectivity.
+const MAX_FIT_STEPS: usize = 64;
+
+// Where query centres are drawn from: random data points, so that queries
+// follow the data density, or uniformly over the data bounding box.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum CenterDistribution {
+    Data,
+    Uniform,
+}
+
+// Query sizes are either a fixed area, as with SampleQuery::from, or fitted
+// per query so that it holds about the given number of points.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum QuerySize {
+    Area(f64),
+    Selectivity(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkloadGenerator {
+    centers: CenterDistribution,
+    size: QuerySize,
+    min_ratio: f64,
+    max_ratio: f64,
+    hotspots: usize,
+    skew: f64,
+    hotspot_spread: f64,
+    seed: u64,
+}
+
+// A generated query with the number of points it holds.
+#[derive(PartialEq, Clone, Debug)]
+pub struct WorkloadQuery {
+    pub range: MBR,
+    pub count: usize,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum WorkloadError {
+    EmptyData,
+    // Parameter name and its invalid value.
+    InvalidParameter(&'static str, f64),
+}
+
+impl fmt::Display for WorkloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkloadError::EmptyData => write!(f, "expected at least one data point"),
+            WorkloadError::InvalidParameter(name, value) => write!(f, "invalid {} {}", name, value),
+        }
+    }
+}
+
+impl std::error::Error for WorkloadError {}
+
+fn check_positive(name: &'static str, value: f64) -> Result<(), WorkloadError> {
+    if value > 0.0 && value.is_finite() { Ok(()) } else { Err(WorkloadError::InvalidParameter(name, value)) }
+}
+
+impl WorkloadGenerator {
+    pub fn new() -> WorkloadGenerator {
+        WorkloadGenerator {
+            centers: CenterDistribution::Data,
+            size: QuerySize::Selectivity(DEFAULT_SELECTIVITY),
+            min_ratio: 1.0,
+            max_ratio: 1.0,
+            hotspots: 0,
+            skew: 1.0,
+            hotspot_spread: DEFAULT_HOTSPOT_SPREAD,
+            seed: 0,
+        }
+    }
+
+    pub fn centers(mut self, centers: CenterDistribution) -> WorkloadGenerator {
+        self.centers = centers;
+        self
+    }
+
+    pub fn area(mut self, area: f64) -> WorkloadGenerator {
+        self.size = QuerySize::Area(area);
+        self
+    }
+
+    // Target number of points per query.
+    pub fn selectivity(mut self, selectivity: usize) -> WorkloadGenerator {
+        self.size = QuerySize::Selectivity(selectivity);
+        self
+    }
+
+    // Ratios are drawn log-uniformly, so 0.25..4 is as likely tall as wide.
+    pub fn aspect_ratio(mut self, min_ratio: f64, max_ratio: f64) -> WorkloadGenerator {
+        self.min_ratio = min_ratio;
+        self.max_ratio = max_ratio;
+        self
+    }
+
+    // Centres cluster around `hotspots` locations whose popularity follows
+    // a Zipf law with exponent `skew`; 0 hotspots disables clustering.
+    pub fn hotspots(mut self, hotspots: usize, skew: f64) -> WorkloadGenerator {
+        self.hotspots = hotspots;
+        self.skew = skew;
+        self
+    }
+
+    // Half side of the box around a hotspot, as a fraction of the data extent.
+    pub fn hotspot_spread(mut self, hotspot_spread: f64) -> WorkloadGenerator {
+        self.hotspot_spread = hotspot_spread;
+        self
+    }
+
+    // The same seed, data and parameters give the same workload.
+    pub fn seed(mut self, seed: u64) -> WorkloadGenerator {
+        self.seed = seed;
+        self
+    }
+
+    pub fn generate(&self, data: &[Point], count: usize) -> Result<Vec<WorkloadQuery>, WorkloadError> {
+        if data.is_empty() {
+            return Err(WorkloadError::EmptyData);
+        }
+        match self.size {
+            QuerySize::Area(area) => check_positive("area", area)?,
+            QuerySize::Selectivity(selectivity) => check_positive("selectivity", selectivity as f64)?,
+        }
+        check_positive("min_ratio", self.min_ratio)?;
+        check_positive("max_ratio", self.max_ratio)?;
+        if self.min_ratio > self.max_ratio {
+            return Err(WorkloadError::InvalidParameter("min_ratio", self.min_ratio));
+        }
+        if !(self.skew >= 0.0 && self.skew.is_finite()) {
+            return Err(WorkloadError::InvalidParameter("skew", self.skew));
+        }
+        if !(self.hotspot_spread >= 0.0 && self.hotspot_spread.is_finite()) {
+            return Err(WorkloadError::InvalidParameter("hotspot_spread", self.hotspot_spread));
+        }
+
+        let kdtree = KDTree::from(data);
+        let domain = MBR::from_points(data);
+        let mut rng = util::seeded_rng(self.seed);
+        let dist = Uniform::from(0.0f64..1.0f64);
+        let draw_center = |rng: &mut dyn rand::RngCore| match self.centers {
+            CenterDistribution::Data => data[((data.len() as f64 * dist.sample(rng)) as usize).min(data.len() - 1)].clone(),
+            CenterDistribution::Uniform => Point::new(
+                domain.low.x + (domain.high.x - domain.low.x) * dist.sample(rng),
+                domain.low.y + (domain.high.y - domain.low.y) * dist.sample(rng)),
+        };
+        let hotspots: Vec<Point> = (0..self.hotspots).map(|_| draw_center(&mut rng)).collect();
+        let popularity = if hotspots.is_empty() {
+            None
+        } else {
+            let weights: Vec<f64> = (0..hotspots.len()).map(|i| 1.0 / ((i + 1) as f64).powf(self.skew)).collect();
+            Some(AliasTable::from(&weights))
+        };
+        let spread_x = (domain.high.x - domain.low.x) * self.hotspot_spread;
+        let spread_y = (domain.high.y - domain.low.y) * self.hotspot_spread;
+
+        let mut queries: Vec<WorkloadQuery> = Vec::with_capacity(count);
+        for _ in 0..count {
+            let center = match &popularity {
+                None => draw_center(&mut rng),
+                Some(alias) => {
+                    let hotspot = &hotspots[alias.sample(dist.sample(&mut rng), dist.sample(&mut rng))];
+                    Point::new(hotspot.x + spread_x * (2.0 * dist.sample(&mut rng) - 1.0),
+                               hotspot.y + spread_y * (2.0 * dist.sample(&mut rng) - 1.0))
+                }
+            };
+            let ratio = (self.min_ratio.ln() + (self.max_ratio.ln() - self.min_ratio.ln()) * dist.sample(&mut rng)).exp();
+            let range = match self.size {
+                QuerySize::Area(area) => SampleQuery::from(&center, area, ratio, 0).range,
+                QuerySize::Selectivity(selectivity) => fit_query(&kdtree, &domain, &center, ratio, selectivity),
+            };
+            let count = kdtree.range_count(&range);
+            queries.push(WorkloadQuery { range, count });
+        }
+        Ok(queries)
+    }
+}
+
+impl Default for WorkloadGenerator {
+    fn default() -> WorkloadGenerator {
+        WorkloadGenerator::new()
+    }
+}
+
+// Bisects the side of the query (the square root of its area), as the count
+// grows monotonically with it, and keeps the closest to `selectivity`. Queries
+// that cannot reach it cover the whole domain.
+fn fit_query(kdtree: &KDTree, domain: &MBR, center: &Point, ratio: f64, selectivity: usize) -> MBR {
+    let dx = (domain.high.x - domain.low.x).max((center.x - domain.low.x).abs()).max((center.x - domain.high.x).abs());
+    let dy = (domain.high.y - domain.low.y).max((center.y - domain.low.y).abs()).max((center.y - domain.high.y).abs());
+    // Large enough to cover the domain from the centre, and never zero.
+    let max_area = (4.0 * dx * dx / ratio).max(4.0 * dy * dy * ratio).max(f64::MIN_POSITIVE);
+    let (mut low, mut high) = (0.0f64, max_area.sqrt() * 1.01);
+    let mut best = SampleQuery::from(center, high * high, ratio, 0).range;
+    let mut best_error = (kdtree.range_count(&best) as f64 - selectivity as f64).abs();
+    // About 1% off is close enough.
+    let tolerance = selectivity as f64 / 100.0;
+    for _ in 0..MAX_FIT_STEPS {
+        if best_error <= tolerance {
+            break;
+        }
+        let side = (low + high) / 2.0;
+        let range = SampleQuery::from(center, side * side, ratio, 0).range;
+        let count = kdtree.range_count(&range);
+        let error = (count as f64 - selectivity as f64).abs();
+        if error < best_error {
+            best = range;
+            best_error = error;
+        }
+        if count < selectivity { low = side; } else { high = side; }
+    }
+    best
+}
+
+// One query per line as "x1 y1 x2 y2 count", which loader::load_ranges reads
+// back and `range-sampling count` also prints.
+pub fn write_queries<W: Write>(mut writer: W, queries: &[WorkloadQuery]) -> std::io::Result<()> {
+    for query in queries.iter() {
+        writeln!(writer, "{} {} {} {} {}", query.range.low.x, query.range.low.y, query.range.high.x, query.range.high.y, query.count)?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,96 @@
+// Synthetic datasets and brute-force references shared by the integration
+// tests. Every generator is seeded, so a failing case can be replayed.
+#![allow(dead_code)]
+
+use range_sampling::geo::{MBR, Point};
+use range_sampling::util;
+use rand::distributions::{Uniform, Distribution};
+use std::collections::HashMap;
+
+pub fn uniform_points(n: usize, seed: u64) -> Vec<Point> {
+    let mut rng = util::seeded_rng(seed);
+    let dist = Uniform::from(0.0f64..100.0f64);
+    (0..n).map(|_| Point::new(dist.sample(&mut rng), dist.sample(&mut rng))).collect()
+}
+
+// Most points in a tight gaussian cluster around (30, 60), the rest uniform.
+pub fn skewed_points(n: usize, seed: u64) -> Vec<Point> {
+    let mut rng = util::seeded_rng(seed);
+    let unit = Uniform::from(0.0f64..1.0f64);
+    (0..n).map(|i| {
+        if i % 5 == 0 {
+            Point::new(100.0 * unit.sample(&mut rng), 100.0 * unit.sample(&mut rng))
+        } else {
+            // Box-Muller.
+            let r = (-2.0 * (1.0 - unit.sample(&mut rng)).ln()).sqrt();
+            let theta = 2.0 * std::f64::consts::PI * unit.sample(&mut rng);
+            Point::new(30.0 + 4.0 * r * theta.cos(), 60.0 + 2.0 * r * theta.sin())
+        }
+    }).collect()
+}
+
+// Integer lattice 0..side in both axes, every point repeated `copies` times,
+// so that queries with integer bounds have points on their edges and corners.
+pub fn lattice_points(side: usize, copies: usize) -> Vec<Point> {
+    let mut points: Vec<Point> = Vec::new();
+    for c in 0..copies {
+        for i in 0..side * side {
+            // Interleave the copies so that duplicates are not adjacent.
+            let i = (i * 7919 + c) % (side * side);
+            points.push(Point::new((i % side) as f64, (i / side) as f64));
+        }
+    }
+    points
+}
+
+// Uneven multiplicities: lattice point i appears 1 + i % 4 times.
+pub fn duplicate_points(side: usize) -> Vec<Point> {
+    let mut points: Vec<Point> = Vec::new();
+    for i in 0..side * side {
+        for _ in 0..1 + i % 4 {
+            points.push(Point::new((i % side) as f64, (i / side) as f64));
+        }
+    }
+    points
+}
+
+pub fn brute_force(data: &[Point], query: &MBR) -> Vec<Point> {
+    data.iter().filter(|p| query.contains(p)).cloned().collect()
+}
+
+pub fn key(p: &Point) -> (u64, u64) {
+    (p.x.to_bits(), p.y.to_bits())
+}
+
+// Number of copies of every distinct point.
+pub fn multiplicities(points: &[Point]) -> HashMap<(u64, u64), usize> {
+    let mut counts: HashMap<(u64, u64), usize> = HashMap::new();
+    for p in points.iter() {
+        *counts.entry(key(p)).or_insert(0) += 1;
+    }
+    counts
+}
+
+pub fn sorted(points: &[Point]) -> Vec<Point> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap().then(a.y.partial_cmp(&b.y).unwrap()));
+    points
+}
+
+pub fn identical_points(n: usize, p: &Point) -> Vec<Point> {
+    vec![p.clone(); n]
+}
+
+// n points on the segment from `from` to `to`, with every fourth point doubled.
+pub fn collinear_points(n: usize, from: &Point, to: &Point) -> Vec<Point> {
+    let mut points: Vec<Point> = Vec::new();
+    for i in 0..n {
+        let t = i as f64 / (n.max(2) - 1) as f64;
+        let p = Point::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t);
+        if i % 4 == 0 {
+            points.push(p.clone());
+        }
+        points.push(p);
+    }
+    points
+}
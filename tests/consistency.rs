@@ -0,0 +1,130 @@
+// Randomized checks that every index agrees with a brute-force scan on range
+// results, counts and sample membership, over generated datasets and queries
+// as well as degenerate inputs. Everything is seeded, so a failing case can be
+// replayed from its label.
+mod common;
+
+use common::*;
+use range_sampling::geo::{MBR, Point};
+use range_sampling::index::named::{INDEX_NAMES, NamedIndex};
+use range_sampling::util;
+use rand::distributions::{Uniform, Distribution};
+
+const K: usize = 50;
+const QUERIES: usize = 40;
+const RANDOM_DATASETS: u64 = 12;
+const MAX_POINTS: usize = 3000;
+const BIGMIN_MAX_INTERVALS: usize = 4;
+
+fn keys(points: &[Point]) -> Vec<(u64, u64)> {
+    sorted(points).iter().map(key).collect()
+}
+
+// Random rectangles over and around the domain 0..100, a quarter of them
+// cornered at data points and some degenerate to a line or a single point.
+fn random_queries(data: &[Point], count: usize, seed: u64) -> Vec<MBR> {
+    let mut rng = util::seeded_rng(seed);
+    let coordinate = Uniform::from(-10.0f64..110.0f64);
+    let unit = Uniform::from(0.0f64..1.0f64);
+    let draw = |rng: &mut dyn rand::RngCore| {
+        if !data.is_empty() && unit.sample(rng) < 0.25 {
+            data[((data.len() as f64 * unit.sample(rng)) as usize).min(data.len() - 1)].clone()
+        } else {
+            Point::new(coordinate.sample(rng), coordinate.sample(rng))
+        }
+    };
+    (0..count).map(|i| {
+        let p = draw(&mut rng);
+        let q = match i % 8 {
+            0 => p.clone(),
+            1 => Point::new(p.x, draw(&mut rng).y),
+            _ => draw(&mut rng),
+        };
+        MBR::from_points(&[p, q])
+    }).collect()
+}
+
+fn check_samples(label: &str, exact: &[Point], samples: &[Point]) {
+    let expected = if exact.is_empty() { 0 } else { K };
+    assert_eq!(samples.len(), expected, "{}: wrong number of samples", label);
+    let members = multiplicities(exact);
+    for s in samples.iter() {
+        assert!(members.contains_key(&key(s)), "{}: sample {} is not in the range", label, s);
+    }
+}
+
+fn check_all_indexes(dataset: &str, data: &[Point], queries: &[MBR]) {
+    for name in INDEX_NAMES.iter() {
+        let mut index = NamedIndex::from_name(name, data).unwrap();
+        for query in queries.iter() {
+            let exact = brute_force(data, query);
+            let label = format!("{} on {} with {}", name, dataset, query);
+            assert_eq!(keys(&index.range(query)), keys(&exact), "{}: range differs", label);
+            assert_eq!(index.range_count(query), exact.len(), "{}: count differs", label);
+            check_samples(&label, &exact, &index.range_sampling(query, K));
+            if let NamedIndex::ZV(tree) = &index {
+                check_samples(&format!("{} (bigmin)", label), &exact, &tree.bigmin_range_sampling(query, K, BIGMIN_MAX_INTERVALS));
+            }
+            if let Some(samples) = index.region_range_sampling(query, K) {
+                check_samples(&format!("{} (region)", label), &exact, &samples);
+            }
+        }
+    }
+}
+
+fn rect(x1: f64, y1: f64, x2: f64, y2: f64) -> MBR {
+    MBR::new(&Point::new(x1, y1), &Point::new(x2, y2))
+}
+
+#[test]
+fn random_datasets() {
+    let mut rng = util::seeded_rng(50);
+    let size = Uniform::from(0..MAX_POINTS);
+    for seed in 0..RANDOM_DATASETS {
+        let n = size.sample(&mut rng);
+        let (kind, data) = match seed % 3 {
+            0 => ("uniform", uniform_points(n, seed)),
+            1 => ("skewed", skewed_points(n, seed)),
+            _ => ("lattice", lattice_points(1 + n % 60, 1 + seed as usize % 3)),
+        };
+        let dataset = format!("{} data of {} points (seed {})", kind, data.len(), seed);
+        check_all_indexes(&dataset, &data, &random_queries(&data, QUERIES, seed));
+    }
+}
+
+#[test]
+fn empty_data() {
+    check_all_indexes("empty data", &[], &[rect(0.0, 0.0, 100.0, 100.0), rect(5.0, 5.0, 5.0, 5.0)]);
+}
+
+#[test]
+fn single_point() {
+    let data = vec![Point::new(42.0, 17.0)];
+    let mut queries = random_queries(&data, QUERIES, 1);
+    queries.extend_from_slice(&[rect(42.0, 17.0, 42.0, 17.0), rect(0.0, 0.0, 41.0, 100.0), rect(42.0, 0.0, 100.0, 16.0)]);
+    check_all_indexes("a single point", &data, &queries);
+}
+
+#[test]
+fn identical_points_data() {
+    let data = identical_points(2000, &Point::new(42.0, 17.0));
+    let mut queries = random_queries(&data, QUERIES, 2);
+    queries.extend_from_slice(&[rect(42.0, 17.0, 42.0, 17.0), rect(0.0, 0.0, 41.0, 100.0), rect(43.0, 0.0, 100.0, 100.0)]);
+    check_all_indexes("identical points", &data, &queries);
+}
+
+#[test]
+fn collinear_data() {
+    let lines = [
+        ("horizontal", Point::new(0.0, 50.0), Point::new(100.0, 50.0)),
+        ("vertical", Point::new(30.0, 0.0), Point::new(30.0, 100.0)),
+        ("diagonal", Point::new(0.0, 0.0), Point::new(100.0, 100.0)),
+    ];
+    for (seed, (line, from, to)) in lines.iter().enumerate() {
+        let data = collinear_points(2000, from, to);
+        let mut queries = random_queries(&data, QUERIES, 3 + seed as u64);
+        // Off the line, on it, and across it.
+        queries.extend_from_slice(&[rect(60.0, 10.0, 90.0, 40.0), rect(0.0, 50.0, 100.0, 50.0), rect(30.0, 30.0, 30.0, 30.0), rect(20.0, 20.0, 70.0, 70.0)]);
+        check_all_indexes(&format!("{} collinear points", line), &data, &queries);
+    }
+}
@@ -0,0 +1,136 @@
+// Goodness-of-fit checks that every sampler draws uniformly from the exact
+// range result. Samplers use unseeded generators, so the thresholds are set
+// at a significance of 1e-6 to keep spurious failures negligible.
+mod common;
+
+use common::*;
+use range_sampling::geo::{MBR, Point};
+use range_sampling::index::named::{INDEX_NAMES, NamedIndex};
+use std::collections::HashMap;
+
+const SAMPLES: usize = 20000;
+const MAX_BINS: usize = 100;
+// Standard normal quantile and KS coefficient for a significance of 1e-6.
+const Z_CRITICAL: f64 = 4.753;
+const KS_CRITICAL: f64 = 2.697;
+const BIGMIN_MAX_INTERVALS: usize = 4;
+
+// Wilson-Hilferty approximation of the chi-square quantile.
+fn chi_square_critical(df: usize) -> f64 {
+    let df = df as f64;
+    let a = 2.0 / (9.0 * df);
+    df * (1.0 - a + Z_CRITICAL * a.sqrt()).powi(3)
+}
+
+// Distinct points of the range in sorted order are grouped into bins of
+// roughly equal expected counts; duplicates weigh by their multiplicity.
+fn chi_square(exact: &[Point], samples: &[Point]) -> (f64, usize) {
+    let multiplicity = multiplicities(exact);
+    let mut distinct = sorted(exact);
+    distinct.dedup();
+    let bins = MAX_BINS.min(distinct.len()).min(samples.len() / 5).max(2);
+    let mut bin_of: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut expected = vec![0.0f64; bins];
+    let mut seen = 0;
+    for p in distinct.iter() {
+        let bin = (seen * bins / exact.len()).min(bins - 1);
+        let m = multiplicity[&key(p)];
+        bin_of.insert(key(p), bin);
+        expected[bin] += m as f64 / exact.len() as f64 * samples.len() as f64;
+        seen += m;
+    }
+    let mut observed = vec![0.0f64; bins];
+    for s in samples.iter() {
+        observed[bin_of[&key(s)]] += 1.0;
+    }
+    let used: Vec<(f64, f64)> = observed.into_iter().zip(expected).filter(|(_, e)| *e > 0.0).collect();
+    let statistic = used.iter().map(|(o, e)| (o - e) * (o - e) / e).sum();
+    (statistic, used.len() - 1)
+}
+
+// One-sample KS distance between the samples' coordinates and the discrete
+// distribution of that coordinate over the exact result.
+fn ks_distance(exact: &[Point], samples: &[Point], coordinate: &dyn Fn(&Point) -> f64) -> f64 {
+    let mut population: Vec<f64> = exact.iter().map(coordinate).collect();
+    let mut drawn: Vec<f64> = samples.iter().map(coordinate).collect();
+    population.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    drawn.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut distance = 0.0f64;
+    let (mut i, mut j) = (0, 0);
+    // Compare both CDFs after every distinct value.
+    while i < population.len() || j < drawn.len() {
+        let value = if j >= drawn.len() || (i < population.len() && population[i] <= drawn[j]) { population[i] } else { drawn[j] };
+        while i < population.len() && population[i] <= value { i += 1; }
+        while j < drawn.len() && drawn[j] <= value { j += 1; }
+        distance = distance.max((i as f64 / population.len() as f64 - j as f64 / drawn.len() as f64).abs());
+    }
+    distance
+}
+
+fn check_uniform(label: &str, exact: &[Point], samples: &[Point]) {
+    assert_eq!(samples.len(), SAMPLES, "{}: expected {} samples", label, SAMPLES);
+    let members = multiplicities(exact);
+    for s in samples.iter() {
+        assert!(members.contains_key(&key(s)), "{}: sample {} is not in the range", label, s);
+    }
+    let (statistic, df) = chi_square(exact, samples);
+    assert!(statistic <= chi_square_critical(df),
+        "{}: chi-square {} exceeds {} with {} degrees of freedom", label, statistic, chi_square_critical(df), df);
+    let ks_critical = KS_CRITICAL / (samples.len() as f64).sqrt();
+    for (axis, coordinate) in [("x", &(|p: &Point| p.x) as &dyn Fn(&Point) -> f64), ("y", &|p: &Point| p.y)].iter() {
+        let distance = ks_distance(exact, samples, coordinate);
+        assert!(distance <= ks_critical, "{}: KS distance {} on {} exceeds {}", label, distance, axis, ks_critical);
+    }
+}
+
+fn check_all_samplers(dataset: &str, data: &[Point], queries: &[MBR]) {
+    for name in INDEX_NAMES.iter() {
+        let mut index = NamedIndex::from_name(name, data).unwrap();
+        for query in queries.iter() {
+            let exact = brute_force(data, query);
+            assert!(!exact.is_empty(), "{}: query {} is empty", dataset, query);
+            let label = format!("{} on {} with {}", name, dataset, query);
+            let samples = index.range_sampling(query, SAMPLES);
+            check_uniform(&label, &exact, &samples);
+            if let NamedIndex::ZV(tree) = &index {
+                let samples = tree.bigmin_range_sampling(query, SAMPLES, BIGMIN_MAX_INTERVALS);
+                check_uniform(&format!("{} (bigmin)", label), &exact, &samples);
+            }
+            if index.supports_regions() {
+                let samples = index.region_range_sampling(query, SAMPLES).unwrap();
+                check_uniform(&format!("{} (region)", label), &exact, &samples);
+            }
+        }
+    }
+}
+
+fn rect(x1: f64, y1: f64, x2: f64, y2: f64) -> MBR {
+    MBR::new(&Point::new(x1, y1), &Point::new(x2, y2))
+}
+
+#[test]
+fn uniform_data() {
+    let data = uniform_points(20000, 1);
+    check_all_samplers("uniform data", &data, &[rect(10.0, 20.0, 35.0, 60.0), rect(0.0, 0.0, 100.0, 100.0)]);
+}
+
+#[test]
+fn skewed_data() {
+    let data = skewed_points(20000, 2);
+    // Inside the dense cluster, and straddling its edge into the sparse part.
+    check_all_samplers("skewed data", &data, &[rect(28.0, 58.0, 33.0, 62.0), rect(25.0, 40.0, 60.0, 75.0)]);
+}
+
+#[test]
+fn duplicate_points_data() {
+    let data = duplicate_points(60);
+    check_all_samplers("duplicate points", &data, &[rect(5.0, 5.0, 30.0, 40.0), rect(0.0, 0.0, 59.0, 59.0)]);
+}
+
+#[test]
+fn boundary_points_data() {
+    // Integer bounds put lattice points on every edge and corner of the
+    // queries, including the edges of the data bounding box.
+    let data = lattice_points(100, 3);
+    check_all_samplers("boundary points", &data, &[rect(10.0, 10.0, 20.0, 45.0), rect(0.0, 90.0, 99.0, 99.0), rect(42.0, 0.0, 42.0, 99.0)]);
+}